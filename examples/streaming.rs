@@ -1,6 +1,6 @@
 //! Streaming example demonstrating real-time response processing
 
-use edgee::{Edgee, Message};
+use edgee::{Edgee, Message, ResponseStreamExt};
 use tokio_stream::StreamExt;
 
 #[tokio::main]
@@ -61,18 +61,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n");
 
     println!("=== Collecting Full Response from Stream ===");
-    let mut stream = client.stream("gpt-4o", "Say hello in 5 languages").await?;
-
-    let mut full_text = String::new();
-    while let Some(result) = stream.next().await {
-        if let Ok(chunk) = result {
-            if let Some(text) = chunk.text() {
-                full_text.push_str(text);
-            }
-        }
-    }
-
-    println!("Full response: {}", full_text);
+    let response = client
+        .stream("gpt-4o", "Say hello in 5 languages")
+        .await?
+        .collect_response()
+        .await?;
+
+    println!("Full response: {}", response.text().unwrap_or(""));
+    println!("Finish reason: {:?}", response.finish_reason());
 
     Ok(())
 }
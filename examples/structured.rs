@@ -0,0 +1,42 @@
+//! Structured output example deserializing replies into Rust types
+//!
+//! Run with: cargo run --example structured --features schemars
+
+use edgee::{Edgee, InputObject, Message, ResponseFormat};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema)]
+struct Country {
+    name: String,
+    capital: String,
+    population: u64,
+    languages: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let client = Edgee::from_env()?;
+
+    println!("=== Typed Structured Output ===");
+    let country: Country = client
+        .send_structured("gpt-4o", "Give me facts about France")
+        .await?;
+    println!("Name: {}", country.name);
+    println!("Capital: {}", country.capital);
+    println!("Population: {}", country.population);
+    println!("Languages: {}\n", country.languages.join(", "));
+
+    println!("=== JSON Object Mode ===");
+    let input = InputObject::new(vec![
+        Message::system("Reply with a JSON object containing a `colors` array."),
+        Message::user("List the colors of the French flag"),
+    ])
+    .with_response_format(ResponseFormat::JsonObject);
+
+    let response = client.send("gpt-4o", input).await?;
+    let colors: serde_json::Value = response.parse()?;
+    println!("{}", colors);
+
+    Ok(())
+}
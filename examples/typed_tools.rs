@@ -0,0 +1,113 @@
+//! Typed tool calling example: schemas and argument parsing derived from Rust types
+//!
+//! Run with: cargo run --example typed_tools --features schemars
+
+use edgee::{Edgee, EdgeeTool, InputObject, Message};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct WeatherArgs {
+    /// The city and state, e.g. San Francisco, CA
+    location: String,
+    /// The temperature unit
+    unit: Option<Unit>,
+}
+
+struct GetWeather;
+
+impl EdgeeTool for GetWeather {
+    type Args = WeatherArgs;
+    const NAME: &'static str = "get_weather";
+    const DESCRIPTION: Option<&'static str> = Some("Get the current weather for a location");
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct CalculateArgs {
+    /// The operation to perform
+    operation: Operation,
+    /// First operand
+    a: f64,
+    /// Second operand
+    b: f64,
+}
+
+struct Calculate;
+
+impl EdgeeTool for Calculate {
+    type Args = CalculateArgs;
+    const NAME: &'static str = "calculate";
+    const DESCRIPTION: Option<&'static str> = Some("Perform a mathematical calculation");
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let client = Edgee::from_env()?;
+
+    let mut messages = vec![Message::user(
+        "What's the weather in San Francisco? Also, what's 15 multiplied by 7?",
+    )];
+    let tools = vec![GetWeather::definition(), Calculate::definition()];
+
+    let input = InputObject::new(messages.clone()).with_tools(tools.clone());
+    let response = client.send("gpt-4o", input).await?;
+
+    let Some(tool_calls) = response.tool_calls() else {
+        println!("{}", response.text().unwrap_or(""));
+        return Ok(());
+    };
+
+    if let Some(message) = response.message() {
+        messages.push(message.clone());
+    }
+
+    for call in tool_calls {
+        let result = if GetWeather::matches(call) {
+            let args = GetWeather::parse_args(call)?;
+            let unit = match args.unit {
+                Some(Unit::Celsius) => "22°C",
+                _ => "72°F",
+            };
+            format!("The weather in {} is sunny, {}", args.location, unit)
+        } else if Calculate::matches(call) {
+            let args = Calculate::parse_args(call)?;
+            let value = match args.operation {
+                Operation::Add => args.a + args.b,
+                Operation::Subtract => args.a - args.b,
+                Operation::Multiply => args.a * args.b,
+                Operation::Divide => args.a / args.b,
+            };
+            format!("The result is {}", value)
+        } else {
+            "Unknown function".to_string()
+        };
+
+        println!(
+            "{}({}) -> {}",
+            call.function.name, call.function.arguments, result
+        );
+        messages.push(Message::tool(call.id.clone(), result));
+    }
+
+    let input = InputObject::new(messages).with_tools(tools);
+    let final_response = client.send("gpt-4o", input).await?;
+    println!("\nFinal response:\n{}", final_response.text().unwrap_or(""));
+
+    Ok(())
+}
@@ -0,0 +1,377 @@
+use crate::{
+    client::{self, Input},
+    error::{Error, Result},
+    middleware::Request,
+    models::{EdgeeConfig, SendResponse},
+    retry::Failure,
+    telemetry::{self, RequestSpan},
+};
+#[cfg(feature = "stream")]
+use crate::{
+    models::{StreamChunk, StreamErrorMode},
+    sse::{SseDecoder, SseEvent},
+};
+use reqwest::{
+    blocking::{Client, Response},
+    Method,
+};
+#[cfg(feature = "stream")]
+use std::{collections::VecDeque, io::Read};
+
+/// Synchronous client for the Edgee AI Gateway
+///
+/// Mirrors [`crate::Edgee`] for programs that do not run an async runtime.
+/// It must not be used from within an async context. Requests are built and
+/// retried like those of the async client, but the middleware of the
+/// configuration are not run.
+///
+/// # Example
+/// ```no_run
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::blocking::Edgee;
+///
+/// let client = Edgee::from_env()?;
+/// let response = client.send("gpt-4o", "Hello, world!")?;
+/// println!("{}", response.text().unwrap_or(""));
+///
+/// # #[cfg(feature = "stream")]
+/// for chunk in client.stream("gpt-4o", "Tell me a story")? {
+///     if let Some(text) = chunk?.text() {
+///         print!("{}", text);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Edgee {
+    config: EdgeeConfig,
+    client: Client,
+}
+
+impl Edgee {
+    /// Create a new client with the given configuration
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client cannot be built from the configuration.
+    /// Use [`Edgee::try_new`] to handle this case.
+    pub fn new(config: EdgeeConfig) -> Self {
+        Self::try_new(config).expect("failed to build the HTTP client")
+    }
+
+    /// Create a new client, returning an error if the HTTP client cannot be
+    /// built from the configuration
+    ///
+    /// The read timeout of the configuration is not supported and ignored.
+    pub fn try_new(config: EdgeeConfig) -> Result<Self> {
+        let mut builder = Client::builder().timeout(config.timeout);
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+        for certificate in &config.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        Ok(Self::with_http_client(config, client))
+    }
+
+    /// Create a new client using an existing HTTP client
+    ///
+    /// The connection-level settings of the configuration (connect timeout,
+    /// proxy, root certificates) are ignored: they are owned by the given
+    /// client. The total timeout, User-Agent and default headers still apply
+    /// to every request.
+    pub fn with_http_client(config: EdgeeConfig, client: Client) -> Self {
+        Self { config, client }
+    }
+
+    /// Create a new client from environment variables
+    /// Reads EDGEE_API_KEY and optionally EDGEE_BASE_URL
+    pub fn from_env() -> Result<Self> {
+        Self::try_new(EdgeeConfig::from_env()?)
+    }
+
+    /// Create a new client with just an API key (uses default base URL)
+    pub fn with_api_key(api_key: impl Into<String>) -> Self {
+        Self::new(EdgeeConfig::new(api_key))
+    }
+
+    /// Configuration of the client
+    pub fn config(&self) -> &EdgeeConfig {
+        &self.config
+    }
+
+    /// Send a chat completion request
+    ///
+    /// Failed requests are retried according to [`EdgeeConfig::retry`].
+    pub fn send(&self, model: impl Into<String>, input: impl Into<Input>) -> Result<SendResponse> {
+        let model = model.into();
+        let input = input.into().into_object();
+        let tags = input.tags.clone().unwrap_or_default();
+        let telemetry = RequestSpan::new("chat", &model, &tags, false);
+        let body = client::Edgee::build_body(model, input.into(), false)?;
+
+        let send_response: SendResponse = telemetry
+            .in_scope(|| Ok(serde_json::from_slice(&self.execute(body)?.bytes()?)?))
+            .map_err(|e| telemetry.record_error(e))?;
+        telemetry.record_response(&send_response);
+        if let Some(tracker) = &self.config.cost_tracker {
+            tracker.record(&send_response, &tags);
+        }
+        Ok(send_response)
+    }
+
+    /// Send a chat completion request with streaming
+    ///
+    /// Returns an iterator over the chunks, reading the response as they are
+    /// consumed. Failed requests are retried according to
+    /// [`EdgeeConfig::retry`] until the response headers are received.
+    #[cfg(feature = "stream")]
+    pub fn stream(&self, model: impl Into<String>, input: impl Into<Input>) -> Result<ChunkIter> {
+        let model = model.into();
+        let input = input.into().into_object();
+        let tags = input.tags.clone().unwrap_or_default();
+        let telemetry = RequestSpan::new("chat", &model, &tags, true);
+        let body = client::Edgee::build_body(model, input.into(), true)?;
+
+        let response = telemetry
+            .in_scope(|| self.execute(body))
+            .map_err(|e| telemetry.record_error(e))?;
+        Ok(ChunkIter {
+            response,
+            decoder: SseDecoder::new(),
+            pending: VecDeque::new(),
+            done: false,
+            mode: self.config.stream_errors,
+            usage: self
+                .config
+                .cost_tracker
+                .clone()
+                .map(|tracker| crate::cost::StreamUsage::new(tracker, tags)),
+            telemetry,
+        })
+    }
+
+    /// Post a chat completion request, retrying according to the configured policy
+    fn execute(&self, body: serde_json::Value) -> Result<Response> {
+        let request = client::Edgee::build_request(
+            &self.config,
+            Method::POST,
+            client::CHAT_COMPLETIONS,
+            Some(body),
+        )?;
+        let policy = &self.config.retry;
+        let mut attempt = 1;
+
+        loop {
+            let delay = match self.send_once(&request) {
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    telemetry::record_status(status);
+                    if response.status().is_success() {
+                        return Ok(response);
+                    }
+                    match policy.retry_delay(attempt, Failure::Status(status, response.headers())) {
+                        Some(delay) => delay,
+                        None => {
+                            let headers = response.headers().clone();
+                            let body = response.text().unwrap_or_default();
+                            return Err(client::Edgee::api_error_from_parts(
+                                status, &headers, body,
+                            ));
+                        }
+                    }
+                }
+                Err(e) => match policy.retry_delay(attempt, Failure::Transport(&e)) {
+                    Some(delay) => delay,
+                    None => return Err(e.into()),
+                },
+            };
+
+            std::thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Perform a single HTTP call
+    fn send_once(&self, request: &Request) -> reqwest::Result<Response> {
+        let mut builder = self
+            .client
+            .request(request.method.clone(), &request.url)
+            .headers(request.headers.clone());
+        if let Some(timeout) = request.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.json(body);
+        }
+        builder.send()
+    }
+}
+
+/// Iterator over the chunks of a streaming response
+#[cfg(feature = "stream")]
+pub struct ChunkIter {
+    response: Response,
+    decoder: SseDecoder,
+    pending: VecDeque<SseEvent>,
+    done: bool,
+    mode: StreamErrorMode,
+    usage: Option<crate::cost::StreamUsage>,
+    telemetry: RequestSpan,
+}
+
+#[cfg(feature = "stream")]
+impl Iterator for ChunkIter {
+    type Item = Result<StreamChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buffer = [0; 8192];
+        loop {
+            while let Some(event) = self.pending.pop_front() {
+                let chunk = match client::Edgee::parse_event(event, self.mode) {
+                    Some(Ok(chunk)) => chunk,
+                    Some(Err(e)) => return Some(Err(self.telemetry.record_error(e))),
+                    None => continue,
+                };
+                self.telemetry.record_chunk(&chunk);
+                if let Some(usage) = &mut self.usage {
+                    usage.observe(&chunk);
+                }
+                return Some(Ok(chunk));
+            }
+            if self.done {
+                return None;
+            }
+
+            match self.response.read(&mut buffer) {
+                Ok(0) => {
+                    self.done = true;
+                    self.pending.extend(self.decoder.finish());
+                }
+                Ok(n) => self.pending.extend(self.decoder.push(&buffer[..n])),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(self.telemetry.record_error(Error::Stream {
+                        message: e.to_string(),
+                        payload: None,
+                    })));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl std::fmt::Debug for ChunkIter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkIter")
+            .field("decoder", &self.decoder)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RetryPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn test_send() {
+        let mut server = mockito::Server::new();
+        let limited = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .expect(1)
+            .create();
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer test-key")
+            .match_header("x-team", "search")
+            .match_header("user-agent", client::USER_AGENT)
+            .with_body(
+                r#"{"id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello"},
+                    "finish_reason": "stop"}]}"#,
+            )
+            .create();
+
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_default_header("x-team", "search")
+            .with_retry(RetryPolicy::new().with_base_delay(Duration::from_millis(1)));
+        let response = Edgee::new(config).send("gpt-4o", "Hi").unwrap();
+
+        assert_eq!(response.text(), Some("Hello"));
+        limited.assert();
+        ok.assert();
+    }
+
+    #[test]
+    fn test_send_api_error() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(401)
+            .with_header("x-request-id", "req_1")
+            .with_body(r#"{"error": {"message": "Invalid API key"}}"#)
+            .create();
+
+        let client = Edgee::new(EdgeeConfig::new("bad-key").with_base_url(server.url()));
+        let err = client.send("gpt-4o", "Hi").unwrap_err();
+        assert!(err.is_auth_error());
+        assert_eq!(
+            err.api_error().unwrap().request_id.as_deref(),
+            Some("req_1")
+        );
+    }
+
+    #[test]
+    fn test_invalid_default_header() {
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url("http://127.0.0.1:1")
+            .with_default_header("x-team", "search\n");
+        let err = Edgee::new(config).send("gpt-4o", "Hi").unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig(_)));
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_stream() {
+        let mut server = mockito::Server::new();
+        let chunk = |content: &str| {
+            format!(
+                r#"data: {{"id": "1", "object": "chat.completion.chunk", "created": 0, "model": "gpt-4o", "choices": [{{"index": 0, "delta": {{"content": "{}"}}, "finish_reason": null}}]}}"#,
+                content
+            )
+        };
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(format!(
+                "{}\n\n: keep-alive\n\n{}\n\ndata: [DONE]\n\n",
+                chunk("Hel"),
+                chunk("lo")
+            ))
+            .create();
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let text: String = client
+            .stream("gpt-4o", "Hi")
+            .unwrap()
+            .map(|chunk| chunk.unwrap().text().unwrap_or_default().to_string())
+            .collect();
+        assert_eq!(text, "Hello");
+    }
+}
@@ -0,0 +1,707 @@
+//! Client-side cache of chat completion responses
+//!
+//! [`ResponseCache`] is a [`Middleware`] serving identical non-streaming
+//! chat completion requests from a [`CacheStore`]. Requests are keyed on a
+//! SHA-256 hash of their URL, their credentials and their canonical JSON
+//! body: model, messages, tools and sampling parameters. Fields that do not
+//! change the reply (`stream`, `tags`, `user`) are ignored, and so is the
+//! order of object keys. Clients using different gateways or API keys never
+//! share entries, even with a shared store.
+//!
+//! # Example
+//! ```no_run
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use edgee::cache::{CacheControl, ResponseCache};
+//! use edgee::{Edgee, EdgeeConfig, InputObject, Message};
+//! use std::time::Duration;
+//!
+//! let cache = ResponseCache::memory(1_000).with_ttl(Duration::from_secs(3600));
+//! let client = Edgee::new(EdgeeConfig::new("your-api-key").with_middleware(cache));
+//!
+//! let response = client.send("gpt-4o", "What is the capital of France?").await?;
+//! let cached = client.send("gpt-4o", "What is the capital of France?").await?;
+//! assert!(!response.cache_hit && cached.cache_hit);
+//!
+//! // Skip the cache lookup, but store the new response
+//! let input = InputObject::new(vec![Message::user("What is the capital of France?")])
+//!     .with_cache_control(CacheControl::refresh());
+//! client.send("gpt-4o", input).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    client::CHAT_COMPLETIONS,
+    error::Result,
+    middleware::{BoxFuture, Middleware, Next, Request, Response},
+};
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Method,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Body fields that do not change the reply
+const IGNORED_FIELDS: &[&str] = &["stream", "stream_options", "tags", "user"];
+
+/// Per-request cache options, set with [`InputObject::with_cache_control`]
+///
+/// [`InputObject::with_cache_control`]: crate::InputObject::with_cache_control
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    /// Do not serve the request from the cache
+    pub no_cache: bool,
+    /// Do not store the response in the cache
+    pub no_store: bool,
+    /// Only serve cached responses younger than this
+    pub max_age: Option<Duration>,
+}
+
+impl CacheControl {
+    /// Default behavior: serve from the cache and store the response
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Neither read nor write the cache
+    pub fn bypass() -> Self {
+        Self {
+            no_cache: true,
+            no_store: true,
+            max_age: None,
+        }
+    }
+
+    /// Skip the cache lookup, but store the new response
+    pub fn refresh() -> Self {
+        Self {
+            no_cache: true,
+            ..Self::default()
+        }
+    }
+
+    /// Only serve cached responses younger than the given age
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+/// Marker set on the extensions of responses served from the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheHit {
+    /// Age of the cached response
+    pub age: Duration,
+}
+
+/// Cached response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// JSON body of the response
+    pub body: String,
+    /// When the response was received
+    pub created_at: SystemTime,
+}
+
+impl CacheEntry {
+    /// Create an entry received now
+    pub fn new(body: impl Into<String>) -> Self {
+        Self {
+            body: body.into(),
+            created_at: SystemTime::now(),
+        }
+    }
+
+    /// Time elapsed since the response was received
+    pub fn age(&self) -> Duration {
+        self.created_at.elapsed().unwrap_or_default()
+    }
+}
+
+/// Storage backend of a [`ResponseCache`]
+///
+/// Stores are best-effort: failing to read or write an entry is not an error.
+pub trait CacheStore: Send + Sync + 'static {
+    /// Get the entry stored under the key
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    /// Store an entry, replacing any previous one
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    /// Remove the entry stored under the key
+    fn remove(&self, key: &str);
+
+    /// Remove all the entries
+    fn clear(&self);
+}
+
+/// In-memory store evicting the least recently used entries
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    inner: Mutex<LruState>,
+}
+
+/// Entries of a [`MemoryCache`] and their order of use
+#[derive(Debug, Default)]
+struct LruState {
+    entries: HashMap<String, (u64, CacheEntry)>,
+    /// Keys by last use, oldest first
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl LruState {
+    /// Mark the key as used now
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        if let Some((last_use, _)) = self.entries.get_mut(key) {
+            self.order.remove(last_use);
+            *last_use = self.tick;
+            self.order.insert(self.tick, key.to_string());
+        }
+    }
+}
+
+impl MemoryCache {
+    /// Create a store holding at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Number of stored entries
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether no entry is stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruState> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut state = self.lock();
+        state.touch(key);
+        state.entries.get(key).map(|(_, entry)| entry.clone())
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let mut state = self.lock();
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some((last_use, _)) = state.entries.insert(key.to_string(), (tick, entry)) {
+            state.order.remove(&last_use);
+        }
+        state.order.insert(tick, key.to_string());
+
+        while state.entries.len() > self.capacity {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut state = self.lock();
+        if let Some((last_use, _)) = state.entries.remove(key) {
+            state.order.remove(&last_use);
+        }
+    }
+
+    fn clear(&self) {
+        *self.lock() = LruState::default();
+    }
+}
+
+/// Store keeping one JSON file per entry in a directory
+///
+/// The directory is created when the first entry is stored. It can be shared
+/// between processes and kept between runs.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Create a store in the given directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let data = std::fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let Ok(data) = serde_json::to_vec(&entry) else {
+            return;
+        };
+        // Write to a temporary file first so readers never see partial
+        // entries. Its name is unique so that concurrent writers, in this
+        // process or another, do not overwrite each other's file.
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let tmp = self.dir.join(format!(
+            "{}.{}.{}.tmp",
+            key,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&tmp, data))
+            .and_then(|_| std::fs::rename(&tmp, self.path(key)));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = std::fs::remove_file(self.path(key));
+    }
+
+    fn clear(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Temporary files may be left behind by interrupted writes
+            if path
+                .extension()
+                .is_some_and(|ext| ext == "json" || ext == "tmp")
+            {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Middleware serving repeated chat completion requests from a cache
+///
+/// Register it before any other middleware so that cached responses skip
+/// them. Clones share the same store.
+#[derive(Clone)]
+pub struct ResponseCache {
+    store: Arc<dyn CacheStore>,
+    ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    /// Create a cache using the given store
+    pub fn new(store: impl CacheStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            ttl: None,
+        }
+    }
+
+    /// Create a cache keeping at most `capacity` responses in memory
+    pub fn memory(capacity: usize) -> Self {
+        Self::new(MemoryCache::new(capacity))
+    }
+
+    /// Create a cache keeping responses in the given directory
+    pub fn disk(dir: impl Into<PathBuf>) -> Self {
+        Self::new(DiskCache::new(dir))
+    }
+
+    /// Expire cached responses after the given duration
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Remove all the cached responses
+    pub fn clear(&self) {
+        self.store.clear();
+    }
+
+    /// Cache key of a request, or `None` if it is not cacheable
+    ///
+    /// Only non-streaming chat completion requests are cached. The base URL
+    /// may carry a path prefix, such as a proxy mount point.
+    pub fn key(request: &Request) -> Option<String> {
+        let body = request.body.as_ref()?;
+        if request.method != Method::POST
+            || !request.path().ends_with(CHAT_COMPLETIONS)
+            || body.get("stream").and_then(|v| v.as_bool()) == Some(true)
+        {
+            return None;
+        }
+
+        // The credentials only enter the key through their own hash
+        let credentials = request
+            .headers
+            .get(AUTHORIZATION)
+            .map(|value| Sha256::digest(value.as_bytes()))
+            .unwrap_or_default();
+        let mut canonical = format!("{}\n{:x}\n", request.url, credentials);
+        match body {
+            serde_json::Value::Object(fields) => {
+                let fields = fields
+                    .iter()
+                    .filter(|(name, _)| !IGNORED_FIELDS.contains(&name.as_str()));
+                write_object(fields, &mut canonical);
+            }
+            other => write_canonical(other, &mut canonical),
+        }
+
+        let digest = Sha256::digest(canonical.as_bytes());
+        let mut key = String::with_capacity(64);
+        for byte in digest {
+            let _ = write!(key, "{:02x}", byte);
+        }
+        Some(key)
+    }
+
+    /// Whether an entry of the given age can be served
+    fn is_fresh(&self, age: Duration, control: &CacheControl) -> bool {
+        self.ttl.map_or(true, |ttl| age < ttl) && control.max_age.map_or(true, |max| age < max)
+    }
+}
+
+impl Middleware for ResponseCache {
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let Some(key) = Self::key(&request) else {
+                return next.run(request).await;
+            };
+            let control = request
+                .extensions
+                .get::<CacheControl>()
+                .copied()
+                .unwrap_or_default();
+
+            if !control.no_cache {
+                if let Some(entry) = self.store.get(&key) {
+                    let age = entry.age();
+                    if self.is_fresh(age, &control) {
+                        let mut response = Response::new(200, entry.body);
+                        response
+                            .headers
+                            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+                        response.extensions.insert(CacheHit { age });
+                        return Ok(response);
+                    }
+                    if self.ttl.is_some_and(|ttl| age >= ttl) {
+                        self.store.remove(&key);
+                    }
+                }
+            }
+
+            let mut response = next.run(request).await?;
+            if control.no_store || !response.is_success() {
+                return Ok(response);
+            }
+
+            let body = std::mem::replace(&mut response.body, crate::middleware::Body::empty())
+                .bytes()
+                .await?;
+            if let Ok(text) = std::str::from_utf8(&body) {
+                self.store.put(&key, CacheEntry::new(text));
+            }
+            response.body = body.into();
+            Ok(response)
+        })
+    }
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Write a JSON value with object keys sorted
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(fields) => write_object(fields.iter(), out),
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+fn write_object<'v>(
+    fields: impl Iterator<Item = (&'v String, &'v serde_json::Value)>,
+    out: &mut String,
+) {
+    let mut fields: Vec<_> = fields.collect();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+
+    out.push('{');
+    for (i, (name, value)) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&serde_json::Value::from(name.as_str()).to_string());
+        out.push(':');
+        write_canonical(value, out);
+    }
+    out.push('}');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Edgee, EdgeeConfig, InputObject, Message};
+    use serde_json::json;
+
+    fn request(body: serde_json::Value) -> Request {
+        Request::new(Method::POST, "https://api.edgee.ai/v1/chat/completions").with_body(body)
+    }
+
+    #[test]
+    fn test_key_is_canonical() {
+        let key = |body| ResponseCache::key(&request(body)).unwrap();
+        let base = key(json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Hi"}]}));
+
+        assert_eq!(
+            base,
+            key(json!({
+                "messages": [{"content": "Hi", "role": "user"}],
+                "model": "gpt-4o",
+                "stream": false,
+                "tags": ["eval"]
+            }))
+        );
+        assert_ne!(
+            base,
+            key(
+                json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Hi"}], "temperature": 0.5})
+            )
+        );
+        assert_ne!(
+            base,
+            key(json!({"model": "gpt-4o-mini", "messages": [{"role": "user", "content": "Hi"}]}))
+        );
+
+        assert!(ResponseCache::key(&request(json!({"model": "gpt-4o", "stream": true}))).is_none());
+        let models = Request::new(Method::GET, "https://api.edgee.ai/v1/models");
+        assert!(ResponseCache::key(&models).is_none());
+    }
+
+    #[test]
+    fn test_key_depends_on_gateway_and_credentials() {
+        let body = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Hi"}]});
+        let config =
+            |base_url: &str, api_key: &str| EdgeeConfig::new(api_key).with_base_url(base_url);
+        let key = |config: &EdgeeConfig| {
+            let request =
+                Edgee::build_request(config, Method::POST, CHAT_COMPLETIONS, Some(body.clone()))
+                    .unwrap();
+            ResponseCache::key(&request).unwrap()
+        };
+
+        let base = key(&config("https://a.example.com", "key-1"));
+        assert_eq!(base, key(&config("https://a.example.com", "key-1")));
+        assert_ne!(base, key(&config("https://b.example.com", "key-1")));
+        assert_ne!(base, key(&config("https://a.example.com", "key-2")));
+    }
+
+    #[test]
+    fn test_key_with_base_url_path_prefix() {
+        let config = EdgeeConfig::new("key-1").with_base_url("https://proxy.example.com/edgee");
+        let body = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Hi"}]});
+        let request =
+            Edgee::build_request(&config, Method::POST, CHAT_COMPLETIONS, Some(body)).unwrap();
+        assert_eq!(request.path(), "/edgee/v1/chat/completions");
+        assert!(ResponseCache::key(&request).is_some());
+
+        let models = Edgee::build_request(&config, Method::GET, "/v1/models", None).unwrap();
+        assert!(ResponseCache::key(&models).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clients_with_other_base_urls_do_not_share_entries() {
+        let body = json!({
+            "id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Paris"}, "finish_reason": "stop"}]
+        })
+        .to_string();
+        let mut first_server = mockito::Server::new_async().await;
+        let first_mock = first_server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(&body)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut second_server = mockito::Server::new_async().await;
+        let second_mock = second_server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(&body)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let cache = ResponseCache::memory(10);
+        let client = |url: String| {
+            Edgee::new(
+                EdgeeConfig::new("test-key")
+                    .with_base_url(url)
+                    .with_middleware(cache.clone()),
+            )
+        };
+
+        let first = client(first_server.url());
+        assert!(!first.send("gpt-4o", "Hi").await.unwrap().cache_hit);
+        assert!(first.send("gpt-4o", "Hi").await.unwrap().cache_hit);
+        let second = client(second_server.url());
+        assert!(!second.send("gpt-4o", "Hi").await.unwrap().cache_hit);
+
+        first_mock.assert_async().await;
+        second_mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(2);
+        cache.put("a", CacheEntry::new("1"));
+        cache.put("b", CacheEntry::new("2"));
+        assert!(cache.get("a").is_some());
+        cache.put("c", CacheEntry::new("3"));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a").unwrap().body, "1");
+
+        cache.remove("a");
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let dir = std::env::temp_dir().join(format!("edgee-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir);
+        assert!(cache.get("key").is_none());
+
+        let entry = CacheEntry::new(r#"{"id": "1"}"#);
+        cache.put("key", entry.clone());
+        assert_eq!(DiskCache::new(&dir).get("key"), Some(entry));
+
+        // Leftovers of an interrupted write are cleared too
+        std::fs::write(dir.join("other.1.0.tmp"), "{").unwrap();
+        cache.clear();
+        assert!(cache.get("key").is_none());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_is_cached() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(
+                json!({
+                    "id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "Paris"}, "finish_reason": "stop"}],
+                    "usage": {"prompt_tokens": 10, "completion_tokens": 1, "total_tokens": 11}
+                })
+                .to_string(),
+            )
+            .expect(5)
+            .create_async()
+            .await;
+
+        let tracker = crate::cost::CostTracker::default();
+        let cache = ResponseCache::memory(10);
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_cost_tracker(tracker.clone())
+            .with_middleware(cache.clone());
+        let client = Edgee::new(config);
+        let input = |control| {
+            InputObject::new(vec![Message::user("Capital of France?")]).with_cache_control(control)
+        };
+
+        let first = client
+            .send("gpt-4o", input(CacheControl::new()))
+            .await
+            .unwrap();
+        let second = client
+            .send("gpt-4o", input(CacheControl::new()))
+            .await
+            .unwrap();
+        assert!(!first.cache_hit);
+        assert!(second.cache_hit);
+        assert_eq!(second.text(), Some("Paris"));
+        assert_eq!(tracker.total().requests, 1);
+
+        let refreshed = client
+            .send("gpt-4o", input(CacheControl::refresh()))
+            .await
+            .unwrap();
+        assert!(!refreshed.cache_hit);
+        let max_age = CacheControl::new().with_max_age(Duration::ZERO);
+        assert!(
+            !client
+                .send("gpt-4o", input(max_age))
+                .await
+                .unwrap()
+                .cache_hit
+        );
+        let bypassed = client
+            .send("gpt-4o", input(CacheControl::bypass()))
+            .await
+            .unwrap();
+        assert!(!bypassed.cache_hit);
+        assert!(
+            client
+                .send("gpt-4o", input(CacheControl::new()))
+                .await
+                .unwrap()
+                .cache_hit
+        );
+        assert_eq!(tracker.total().requests, 4);
+
+        cache.clear();
+        assert!(
+            !client
+                .send("gpt-4o", input(CacheControl::new()))
+                .await
+                .unwrap()
+                .cache_hit
+        );
+        mock.assert_async().await;
+    }
+}
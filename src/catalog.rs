@@ -0,0 +1,294 @@
+use crate::{client::Edgee, error::Result, tokens};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+/// Path of the models endpoint
+const MODELS: &str = "/v1/models";
+
+/// Model available through the gateway
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Model {
+    /// Identifier to use in requests
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    /// Organization owning the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owned_by: Option<String>,
+    /// Provider serving the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// Maximum number of tokens of the prompt and the completion together
+    #[serde(
+        default,
+        alias = "context_window",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub context_length: Option<u32>,
+    /// Maximum number of tokens the model can generate
+    #[serde(
+        default,
+        alias = "max_completion_tokens",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_output_tokens: Option<u32>,
+    /// Features supported by the model (e.g. `tools`, `vision`, `json_schema`)
+    #[serde(
+        default,
+        deserialize_with = "deserialize_capabilities",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub capabilities: Vec<String>,
+}
+
+impl Model {
+    /// Provider of the model, from its metadata or its `provider/` prefix
+    pub fn provider(&self) -> Option<&str> {
+        self.provider
+            .as_deref()
+            .or(self.owned_by.as_deref())
+            .or_else(|| self.id.split_once('/').map(|(provider, _)| provider))
+    }
+
+    /// Whether the model advertises the given capability
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Context window of the model, falling back to the known model limits
+    pub fn context_window(&self) -> Option<u32> {
+        self.context_length
+            .or_else(|| tokens::model_limits(&self.id).map(|limits| limits.context_window))
+    }
+}
+
+/// Response of the models endpoint
+#[derive(Debug, Deserialize)]
+struct ModelList {
+    data: Vec<Model>,
+}
+
+/// Models fetched at a given time
+type CachedModels = (Instant, Vec<Model>);
+
+/// Client-side cache of the model catalog
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ModelCache {
+    inner: Arc<Mutex<Option<CachedModels>>>,
+}
+
+impl ModelCache {
+    /// Cached models, if they were fetched less than `ttl` ago
+    fn get(&self, ttl: std::time::Duration) -> Option<Vec<Model>> {
+        let cache = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        cache
+            .as_ref()
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < ttl)
+            .map(|(_, models)| models.clone())
+    }
+
+    fn set(&self, models: Vec<Model>) {
+        *self.inner.lock().unwrap_or_else(PoisonError::into_inner) = Some((Instant::now(), models));
+    }
+
+    fn clear(&self) {
+        *self.inner.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+impl Edgee {
+    /// List the models available to the API key
+    ///
+    /// The list is cached when [`EdgeeConfig::with_model_cache`] is set.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use edgee::Edgee;
+    ///
+    /// let client = Edgee::from_env()?;
+    /// for model in client.list_models().await? {
+    ///     println!("{} ({:?}, {:?} tokens)", model.id, model.provider(), model.context_window());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`EdgeeConfig::with_model_cache`]: crate::EdgeeConfig::with_model_cache
+    pub async fn list_models(&self) -> Result<Vec<Model>> {
+        let ttl = self.config().model_cache_ttl;
+        if let Some(models) = ttl.and_then(|ttl| self.model_cache().get(ttl)) {
+            return Ok(models);
+        }
+
+        let list: ModelList = self.get_json(MODELS).await?;
+        if ttl.is_some() {
+            self.model_cache().set(list.data.clone());
+        }
+        Ok(list.data)
+    }
+
+    /// Get a single model
+    ///
+    /// Served from the cached list when available. Fails with an API error
+    /// with status 404 when the model does not exist.
+    pub async fn get_model(&self, id: &str) -> Result<Model> {
+        let cached = self
+            .config()
+            .model_cache_ttl
+            .and_then(|ttl| self.model_cache().get(ttl));
+        if let Some(model) = cached.and_then(|models| models.into_iter().find(|m| m.id == id)) {
+            return Ok(model);
+        }
+
+        self.get_json(&format!("{}/{}", MODELS, path_segment(id)))
+            .await
+    }
+
+    /// Forget the cached model list, so the next call fetches it again
+    pub fn clear_model_cache(&self) {
+        self.model_cache().clear();
+    }
+}
+
+/// Percent-encode a value as a single URL path segment
+fn path_segment(value: &str) -> String {
+    let mut url = reqwest::Url::parse("http://localhost/").expect("valid URL");
+    url.path_segments_mut()
+        .expect("URL with a path")
+        .pop_if_empty()
+        .push(value);
+    url.path()[1..].to_string()
+}
+
+/// Deserialize capabilities sent either as a list of names or as a map of flags
+fn deserialize_capabilities<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Capabilities {
+        List(Vec<String>),
+        Flags(HashMap<String, bool>),
+    }
+
+    Ok(match Option::<Capabilities>::deserialize(deserializer)? {
+        Some(Capabilities::List(list)) => list,
+        Some(Capabilities::Flags(flags)) => {
+            let mut list: Vec<String> = flags
+                .into_iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name)
+                .collect();
+            list.sort();
+            list
+        }
+        None => Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EdgeeConfig;
+    use std::time::Duration;
+
+    const MODEL_LIST: &str = r#"{
+        "object": "list",
+        "data": [
+            {"id": "openai/gpt-4o", "object": "model", "created": 1715367049, "owned_by": "openai",
+             "context_length": 128000, "capabilities": ["tools", "vision"]},
+            {"id": "mistral-large-latest", "object": "model",
+             "capabilities": {"tools": true, "vision": false}}
+        ]
+    }"#;
+
+    #[test]
+    fn test_model_metadata() {
+        let list: ModelList = serde_json::from_str(MODEL_LIST).unwrap();
+        let [gpt, mistral] = &list.data[..] else {
+            panic!("expected two models");
+        };
+
+        assert_eq!(gpt.provider(), Some("openai"));
+        assert!(gpt.supports("vision"));
+        assert_eq!(gpt.context_window(), Some(128_000));
+
+        assert_eq!(mistral.provider(), None);
+        assert_eq!(mistral.capabilities, ["tools"]);
+        assert_eq!(mistral.context_window(), Some(131_072));
+    }
+
+    #[tokio::test]
+    async fn test_list_models_is_cached() {
+        let mut server = mockito::Server::new_async().await;
+        let list = server
+            .mock("GET", "/v1/models")
+            .match_header("authorization", "Bearer test-key")
+            .with_body(MODEL_LIST)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_model_cache(Duration::from_secs(60));
+        let client = Edgee::new(config);
+
+        assert_eq!(client.list_models().await.unwrap().len(), 2);
+        let model = client.get_model("mistral-large-latest").await.unwrap();
+        assert!(model.supports("tools"));
+
+        client.clear_model_cache();
+        client.list_models().await.unwrap();
+        list.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_get_model() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/v1/models/gpt-4o")
+            .with_body(r#"{"id": "gpt-4o", "object": "model", "owned_by": "openai"}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/v1/models/unknown")
+            .with_status(404)
+            .with_body(r#"{"error": {"message": "Model not found"}}"#)
+            .create_async()
+            .await;
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let model = client.get_model("gpt-4o").await.unwrap();
+        assert_eq!(model.provider(), Some("openai"));
+
+        let err = client.get_model("unknown").await.unwrap_err();
+        assert_eq!(err.status(), Some(404));
+    }
+
+    #[tokio::test]
+    async fn test_get_model_with_slash_in_id() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/v1/models/openai%2Fgpt-4o%3Fv%231")
+            .with_body(r#"{"id": "openai/gpt-4o?v#1", "object": "model"}"#)
+            .create_async()
+            .await;
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let model = client.get_model("openai/gpt-4o?v#1").await.unwrap();
+        assert_eq!(model.id, "openai/gpt-4o?v#1");
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn test_path_segment() {
+        assert_eq!(path_segment("gpt-4o"), "gpt-4o");
+        assert_eq!(path_segment("ft:gpt-4o:org/a b"), "ft:gpt-4o:org%2Fa%20b");
+    }
+}
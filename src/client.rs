@@ -1,14 +1,40 @@
+#[cfg(feature = "stream")]
+use crate::sse;
 use crate::{
-    error::{Error, Result},
+    catalog::ModelCache,
+    error::{ApiError, Error, Result},
+    middleware::{self, Middleware, Next, Request, Response},
     models::*,
+    telemetry::RequestSpan,
 };
-use bytes::Bytes;
+#[cfg(feature = "stream")]
 use futures::stream::{Stream, StreamExt};
-use reqwest::Client;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Client, Method,
+};
+use serde::de::DeserializeOwned;
 use serde_json::json;
+#[cfg(feature = "stream")]
 use std::pin::Pin;
+use std::sync::Arc;
+
+/// Path of the chat completions endpoint
+pub(crate) const CHAT_COMPLETIONS: &str = "/v1/chat/completions";
+
+/// Default User-Agent header
+pub(crate) const USER_AGENT: &str = concat!("edgee-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Build a header value from the configuration
+fn header_value(value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|e| Error::InvalidConfig(format!("Invalid header value: {}", e)))
+}
 
 /// Input types accepted by the send method
+// Inputs are built right before being consumed by a request, so their size
+// does not matter and boxing would only make matching on them less ergonomic.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Input {
     /// Simple text input (converted to a user message)
@@ -41,14 +67,14 @@ impl From<Vec<Message>> for Input {
     }
 }
 
-/// Parsed input components
-struct ParsedInput {
-    messages: Vec<Message>,
-    tools: Option<Vec<Tool>>,
-    tool_choice: Option<serde_json::Value>,
-    tags: Option<Vec<String>>,
-    enable_compression: Option<bool>,
-    compression_rate: Option<f64>,
+impl Input {
+    /// Convert the input into a structured input object
+    pub fn into_object(self) -> InputObject {
+        match self {
+            Input::Text(text) => InputObject::new(vec![Message::user(text)]),
+            Input::Object(obj) => obj,
+        }
+    }
 }
 
 /// Main client for interacting with the Edgee AI Gateway
@@ -56,14 +82,63 @@ struct ParsedInput {
 pub struct Edgee {
     config: EdgeeConfig,
     client: Client,
+    middlewares: Arc<[Arc<dyn Middleware>]>,
+    models: ModelCache,
 }
 
 impl Edgee {
     /// Create a new Edgee client with the given configuration
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client cannot be built from the configuration
+    /// (e.g. the TLS backend cannot be initialized). Use [`Edgee::try_new`]
+    /// to handle this case.
     pub fn new(config: EdgeeConfig) -> Self {
+        Self::try_new(config).expect("failed to build the HTTP client")
+    }
+
+    /// Create a new Edgee client, returning an error if the HTTP client
+    /// cannot be built from the configuration
+    pub fn try_new(config: EdgeeConfig) -> Result<Self> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = config.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+        for certificate in &config.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| Error::InvalidConfig(e.to_string()))?;
+        Ok(Self::with_http_client(config, client))
+    }
+
+    /// Create a new Edgee client using an existing HTTP client
+    ///
+    /// The connection-level settings of the configuration (connect and read
+    /// timeouts, proxy, root certificates) are ignored: they are owned by the
+    /// given client. The total timeout, User-Agent and default headers still
+    /// apply to every request.
+    pub fn with_http_client(config: EdgeeConfig, client: Client) -> Self {
+        let mut middlewares = config.middlewares.clone();
+        middlewares.push(Arc::new(middleware::Retry(config.retry.clone())));
+        #[cfg(feature = "tracing")]
+        middlewares.push(Arc::new(middleware::Logging));
+
         Self {
             config,
-            client: Client::new(),
+            client,
+            middlewares: middlewares.into(),
+            models: ModelCache::default(),
         }
     }
 
@@ -71,7 +146,7 @@ impl Edgee {
     /// Reads EDGEE_API_KEY and optionally EDGEE_BASE_URL
     pub fn from_env() -> Result<Self> {
         let config = EdgeeConfig::from_env()?;
-        Ok(Self::new(config))
+        Self::try_new(config)
     }
 
     /// Create a new Edgee client with just an API key (uses default base URL)
@@ -79,8 +154,20 @@ impl Edgee {
         Self::new(EdgeeConfig::new(api_key))
     }
 
+    /// Configuration of the client
+    pub fn config(&self) -> &EdgeeConfig {
+        &self.config
+    }
+
+    /// Cache of the model catalog, shared between clones of the client
+    pub(crate) fn model_cache(&self) -> &ModelCache {
+        &self.models
+    }
+
     /// Send a chat completion request (non-streaming)
     ///
+    /// Failed requests are retried according to [`EdgeeConfig::retry`].
+    ///
     /// # Arguments
     /// * `model` - The model to use (e.g., "gpt-4o", "mistral-large-latest")
     /// * `input` - The input (can be a string, InputObject, or `Vec<Message>`)
@@ -101,56 +188,41 @@ impl Edgee {
         model: impl Into<String>,
         input: impl Into<Input>,
     ) -> Result<SendResponse> {
-        let input = input.into();
-        let parsed = self.parse_input(input);
-
-        let mut body = json!({
-            "model": model.into(),
-            "messages": parsed.messages,
-            "stream": false,
-        });
-
-        if let Some(tools) = parsed.tools {
-            body["tools"] = json!(tools);
-        }
-        if let Some(tool_choice) = parsed.tool_choice {
-            body["tool_choice"] = tool_choice;
-        }
-        if let Some(tags) = parsed.tags {
-            body["tags"] = json!(tags);
-        }
-        if let Some(enable_compression) = parsed.enable_compression {
-            body["enable_compression"] = json!(enable_compression);
+        let model = model.into();
+        let input = input.into().into_object();
+        let tags = input.tags.clone().unwrap_or_default();
+        #[cfg(feature = "cache")]
+        let cache_control = input.cache_control;
+        let telemetry = RequestSpan::new("chat", &model, &tags, false);
+        let body = Self::build_body(model, input.into(), false)?;
+
+        let request = self.request(Method::POST, CHAT_COMPLETIONS, Some(body))?;
+        #[cfg(feature = "cache")]
+        let request = {
+            let mut request = request;
+            if let Some(cache_control) = cache_control {
+                request.extensions.insert(cache_control);
+            }
+            request
+        };
+        let send_response = telemetry
+            .instrument(self.send_request(request))
+            .await
+            .map_err(|e| telemetry.record_error(e))?;
+
+        telemetry.record_response(&send_response);
+        // Cached responses cost nothing
+        if let (Some(tracker), false) = (&self.config.cost_tracker, send_response.cache_hit) {
+            tracker.record(&send_response, &tags);
         }
-        if let Some(compression_rate) = parsed.compression_rate {
-            body["compression_rate"] = json!(compression_rate);
-        }
-
-        let response = self
-            .client
-            .post(format!("{}/v1/chat/completions", self.config.base_url))
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let message = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(Error::Api { status, message });
-        }
-
-        let send_response: SendResponse = response.json().await?;
         Ok(send_response)
     }
 
     /// Send a chat completion request with streaming
     ///
-    /// Returns a stream of chunks that can be processed as they arrive
+    /// Returns a stream of chunks that can be processed as they arrive.
+    /// Failed requests are retried according to [`EdgeeConfig::retry`], as
+    /// long as no byte of the stream has been received yet.
     ///
     /// # Arguments
     /// * `model` - The model to use (e.g., "gpt-4o", "mistral-large-latest")
@@ -175,126 +247,292 @@ impl Edgee {
     /// # Ok(())
     /// # }
     /// ```
+    #[cfg(feature = "stream")]
     pub async fn stream(
         &self,
         model: impl Into<String>,
         input: impl Into<Input>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>> {
-        let input = input.into();
-        let parsed = self.parse_input(input);
-
-        let mut body = json!({
-            "model": model.into(),
-            "messages": parsed.messages,
-            "stream": true,
+        let model = model.into();
+        let input = input.into().into_object();
+        let tags = input.tags.clone().unwrap_or_default();
+        let mut telemetry = RequestSpan::new("chat", &model, &tags, true);
+        let body = Self::build_body(model, input.into(), true)?;
+        let request = self.request(Method::POST, CHAT_COMPLETIONS, Some(body))?;
+        let stream = telemetry
+            .instrument(self.execute(request))
+            .await
+            .map_err(|e| telemetry.record_error(e))?
+            .body;
+        let parsed_stream = Self::parse_sse_stream(stream, self.config.stream_errors);
+
+        // Usage is only sent with the last chunks of a stream. It is recorded
+        // and the span is closed when the stream is dropped.
+        let mut usage = self
+            .config
+            .cost_tracker
+            .clone()
+            .map(|tracker| crate::cost::StreamUsage::new(tracker, tags));
+        let tracked_stream = parsed_stream.map(move |chunk| {
+            let chunk = chunk.map_err(|e| telemetry.record_error(e))?;
+            telemetry.record_chunk(&chunk);
+            if let Some(usage) = &mut usage {
+                usage.observe(&chunk);
+            }
+            Ok(chunk)
         });
 
-        if let Some(tools) = parsed.tools {
-            body["tools"] = json!(tools);
-        }
-        if let Some(tool_choice) = parsed.tool_choice {
-            body["tool_choice"] = tool_choice;
-        }
-        if let Some(tags) = parsed.tags {
-            body["tags"] = json!(tags);
-        }
-        if let Some(enable_compression) = parsed.enable_compression {
+        Ok(Box::pin(tracked_stream))
+    }
+
+    /// Send a chat completion request and deserialize the reply into `T`
+    ///
+    /// The JSON schema of `T` is sent as a strict `response_format`,
+    /// replacing any format already set on the input. Fails with
+    /// [`Error::StructuredOutput`] when the reply does not match `T`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use edgee::Edgee;
+    /// use schemars::JsonSchema;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize, JsonSchema)]
+    /// struct Capital {
+    ///     country: String,
+    ///     city: String,
+    /// }
+    ///
+    /// let client = Edgee::from_env()?;
+    /// let capital: Capital = client
+    ///     .send_structured("gpt-4o", "What is the capital of France?")
+    ///     .await?;
+    /// println!("{}", capital.city);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "schemars")]
+    pub async fn send_structured<T>(
+        &self,
+        model: impl Into<String>,
+        input: impl Into<Input>,
+    ) -> Result<T>
+    where
+        T: serde::de::DeserializeOwned + schemars::JsonSchema,
+    {
+        let input = input
+            .into()
+            .into_object()
+            .with_response_format(ResponseFormat::json_schema_for::<T>());
+        self.send(model, input).await?.parse()
+    }
+
+    /// Build the JSON body of a chat completion request
+    pub(crate) fn build_body(
+        model: String,
+        input: Input,
+        stream: bool,
+    ) -> Result<serde_json::Value> {
+        let input = input.into_object();
+
+        let mut body = serde_json::to_value(&input)?;
+        body["model"] = json!(model);
+        body["stream"] = json!(stream);
+
+        // Gateway-internal fields are skipped when serializing the input
+        if let Some(enable_compression) = input.enable_compression {
             body["enable_compression"] = json!(enable_compression);
         }
-        if let Some(compression_rate) = parsed.compression_rate {
+        if let Some(compression_rate) = input.compression_rate {
             body["compression_rate"] = json!(compression_rate);
         }
 
-        let response = self
-            .client
-            .post(format!("{}/v1/chat/completions", self.config.base_url))
-            .header("Authorization", format!("Bearer {}", self.config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&body)
-            .send()
+        Ok(body)
+    }
+
+    /// Post a request and deserialize the JSON response
+    pub(crate) async fn post_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<T> {
+        self.request_json(Method::POST, path, Some(body)).await
+    }
+
+    /// Send a GET request and deserialize the JSON response
+    pub(crate) async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.request_json(Method::GET, path, None).await
+    }
+
+    /// Send a request and deserialize the JSON response
+    async fn request_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<T> {
+        let request = self.request(method, path, body.cloned())?;
+        let bytes = self.execute(request).await?.body.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Send a chat completion request and deserialize the response
+    async fn send_request(&self, request: Request) -> Result<SendResponse> {
+        let response = self.execute(request).await?;
+        #[cfg(feature = "cache")]
+        let cache_hit = response
+            .extensions
+            .get::<crate::cache::CacheHit>()
+            .is_some();
+        #[cfg(not(feature = "cache"))]
+        let cache_hit = false;
+
+        let bytes = response.body.bytes().await?;
+        let mut send_response: SendResponse = serde_json::from_slice(&bytes)?;
+        send_response.cache_hit = cache_hit;
+        Ok(send_response)
+    }
+
+    /// Send a request through the middleware chain
+    ///
+    /// Non-success responses are converted into API errors.
+    pub(crate) async fn execute(&self, request: Request) -> Result<Response> {
+        let response = Next::new(&self.middlewares, &self.client)
+            .run(request)
             .await?;
+        if !response.is_success() {
+            let body = response.body.bytes().await.unwrap_or_default();
+            return Err(Self::api_error_from_parts(
+                response.status,
+                &response.headers,
+                String::from_utf8_lossy(&body).into_owned(),
+            ));
+        }
+        Ok(response)
+    }
 
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let message = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(Error::Api { status, message });
+    /// Build a request to the gateway, with the headers of the configuration
+    pub(crate) fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Request> {
+        Self::build_request(&self.config, method, path, body)
+    }
+
+    /// Build a request to the gateway from a configuration
+    ///
+    /// Shared by the async and blocking clients.
+    pub(crate) fn build_request(
+        config: &EdgeeConfig,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Request> {
+        let mut request = Request::new(method, format!("{}{}", config.base_url, path));
+        request.timeout = config.timeout;
+
+        let user_agent = config.user_agent.as_deref().unwrap_or(USER_AGENT);
+        request
+            .headers
+            .insert(header::USER_AGENT, header_value(user_agent)?);
+        for (name, value) in &config.default_headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                Error::InvalidConfig(format!("Invalid header name {}: {}", name, e))
+            })?;
+            request.headers.insert(name, header_value(value)?);
         }
 
-        let stream = response.bytes_stream();
-        let parsed_stream = Self::parse_sse_stream(stream);
+        let mut authorization = header_value(&format!("Bearer {}", config.api_key))?;
+        authorization.set_sensitive(true);
+        request.headers.insert(header::AUTHORIZATION, authorization);
+        if body.is_some() {
+            request.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+        }
+        request.body = body;
+        Ok(request)
+    }
 
-        Ok(Box::pin(parsed_stream))
+    /// Build an API error from the status, headers and body of a response
+    pub(crate) fn api_error_from_parts(status: u16, headers: &HeaderMap, body: String) -> Error {
+        let request_id = headers
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let mut error = ApiError::from_body(status, body);
+        error.request_id = request_id;
+        error.into()
     }
 
     /// Parse SSE stream into StreamChunk objects
+    #[cfg(feature = "stream")]
     fn parse_sse_stream(
-        stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+        stream: middleware::Body,
+        mode: StreamErrorMode,
     ) -> impl Stream<Item = Result<StreamChunk>> + Send {
-        let mut buffer = String::new();
-
-        stream
-            .map(move |result| {
-                let bytes = result.map_err(Error::Http)?;
-                let text = String::from_utf8_lossy(&bytes);
-                buffer.push_str(&text);
-
-                let mut chunks = Vec::new();
-                while let Some(pos) = buffer.find("\n\n") {
-                    let chunk = buffer[..pos].to_string();
-                    buffer.drain(..pos + 2);
-
-                    if chunk.is_empty() {
-                        continue;
-                    }
-
-                    // Parse SSE format: "data: {...}"
-                    for line in chunk.lines() {
-                        if let Some(data) = line.strip_prefix("data: ") {
-                            if data == "[DONE]" {
-                                continue;
-                            }
-
-                            match serde_json::from_str::<StreamChunk>(data) {
-                                Ok(parsed_chunk) => chunks.push(Ok(parsed_chunk)),
-                                Err(e) => {
-                                    // Skip malformed JSON (similar to Python SDK behavior)
-                                    eprintln!("Failed to parse chunk: {}", e);
-                                }
-                            }
-                        }
-                    }
-                }
+        sse::decode(stream).filter_map(move |event| async move {
+            match event {
+                Ok(event) => Self::parse_event(event, mode),
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
 
-                Ok(chunks)
-            })
-            .flat_map(|result: Result<Vec<Result<StreamChunk>>>| match result {
-                Ok(chunks) => futures::stream::iter(chunks).boxed(),
-                Err(e) => futures::stream::once(async move { Err(e) }).boxed(),
-            })
-    }
-
-    /// Parse input into components
-    fn parse_input(&self, input: Input) -> ParsedInput {
-        match input {
-            Input::Text(text) => ParsedInput {
-                messages: vec![Message::user(text)],
-                tools: None,
-                tool_choice: None,
-                tags: None,
-                enable_compression: None,
-                compression_rate: None,
-            },
-            Input::Object(obj) => ParsedInput {
-                messages: obj.messages,
-                tools: obj.tools,
-                tool_choice: obj.tool_choice,
-                tags: obj.tags,
-                enable_compression: obj.enable_compression,
-                compression_rate: obj.compression_rate,
-            },
+    /// Parse a single server-sent event into a chunk
+    ///
+    /// Returns `None` for events that carry no chunk, and for malformed
+    /// chunks in lenient mode.
+    #[cfg(feature = "stream")]
+    pub(crate) fn parse_event(
+        event: sse::SseEvent,
+        mode: StreamErrorMode,
+    ) -> Option<Result<StreamChunk>> {
+        if event.data.trim() == "[DONE]" {
+            return None;
+        }
+
+        let value = serde_json::from_str::<serde_json::Value>(&event.data).ok();
+        let error = value
+            .as_ref()
+            .and_then(|v| v.get("error"))
+            .filter(|e| !e.is_null());
+        if error.is_some() || event.event == "error" {
+            let message = error
+                .and_then(|e| e.get("message").or(Some(e)))
+                .and_then(|m| m.as_str())
+                .unwrap_or("The gateway sent an error event")
+                .to_string();
+            return Some(Err(Error::Stream {
+                message,
+                payload: Some(event.data),
+            }));
+        }
+
+        // Completion chunks are only sent as `message` events
+        if !event.is_message() {
+            return None;
+        }
+
+        let parsed = match value {
+            Some(value) => serde_json::from_value::<StreamChunk>(value),
+            None => serde_json::from_str::<StreamChunk>(&event.data),
+        };
+        match (parsed, mode) {
+            (Ok(chunk), _) => Some(Ok(chunk)),
+            (Err(e), StreamErrorMode::Lenient) => {
+                crate::telemetry::skipped_chunk(&e, &event.data);
+                None
+            }
+            (Err(e), StreamErrorMode::Strict) => Some(Err(Error::Stream {
+                message: format!("Failed to parse chunk: {}", e),
+                payload: Some(event.data),
+            })),
         }
     }
 }
@@ -302,6 +540,7 @@ impl Edgee {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::RetryPolicy;
 
     #[test]
     fn test_input_conversions() {
@@ -340,7 +579,7 @@ mod tests {
     fn test_message_constructors() {
         let msg = Message::user("hello");
         assert_eq!(msg.role, Role::User);
-        assert_eq!(msg.content.as_deref(), Some("hello"));
+        assert_eq!(msg.text(), Some("hello"));
 
         let msg = Message::system("You are helpful");
         assert_eq!(msg.role, Role::System);
@@ -355,4 +594,354 @@ mod tests {
         assert_eq!(msg.role, Role::Tool);
         assert_eq!(msg.tool_call_id.as_deref(), Some("call-123"));
     }
+
+    fn retry_client(server: &mockito::Server) -> Edgee {
+        Edgee::new(
+            EdgeeConfig::new("test-key")
+                .with_base_url(server.url())
+                .with_retry(
+                    RetryPolicy::new()
+                        .with_base_delay(std::time::Duration::from_millis(1))
+                        .with_jitter(0.0),
+                ),
+        )
+    }
+
+    const COMPLETION: &str = r#"{
+        "id": "test-id",
+        "object": "chat.completion",
+        "created": 1234567890,
+        "model": "gpt-4o",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "Hello"},
+            "finish_reason": "stop"
+        }]
+    }"#;
+
+    #[tokio::test]
+    async fn test_send_retries_rate_limited_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let limited = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .with_body("rate limited")
+            .expect(2)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_body(COMPLETION)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let response = retry_client(&server).send("gpt-4o", "hi").await.unwrap();
+        assert_eq!(response.text(), Some("Hello"));
+        limited.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_gives_up_after_max_attempts() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .with_body("unavailable")
+            .expect(3)
+            .create_async()
+            .await;
+
+        let err = retry_client(&server)
+            .send("gpt-4o", "hi")
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), Some(503));
+        unavailable.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_does_not_retry_client_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let bad_request = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(400)
+            .with_header("x-request-id", "req_123")
+            .with_body(r#"{"error": {"message": "Invalid model", "code": "model_not_found"}}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let err = retry_client(&server)
+            .send("gpt-4o", "hi")
+            .await
+            .unwrap_err();
+        let api_error = err.api_error().unwrap();
+        assert_eq!(api_error.status, 400);
+        assert_eq!(api_error.message, "Invalid model");
+        assert_eq!(api_error.code.as_deref(), Some("model_not_found"));
+        assert_eq!(api_error.request_id.as_deref(), Some("req_123"));
+        bad_request.assert_async().await;
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_stream_retries_before_first_chunk() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(200)
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":1,",
+                "\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+                "data: [DONE]\n\n"
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let stream = retry_client(&server).stream("gpt-4o", "hi").await.unwrap();
+        let chunks: Vec<_> = stream.collect().await;
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].as_ref().unwrap().text(), Some("Hi"));
+        unavailable.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_default_headers_and_user_agent() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("user-agent", "my-app/1.0")
+            .match_header("x-team", "search")
+            .match_header("authorization", "Bearer test-key")
+            .with_status(200)
+            .with_body(COMPLETION)
+            .create_async()
+            .await;
+
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_user_agent("my-app/1.0")
+            .with_default_header("x-team", "search");
+        let client = Edgee::with_http_client(config, Client::new());
+
+        client.send("gpt-4o", "hi").await.unwrap();
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_cost_tracker_records_responses() {
+        let mut server = mockito::Server::new_async().await;
+        let body = serde_json::json!({
+            "id": "test-id",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "gpt-4o-2024-08-06",
+            "choices": [],
+            "usage": {"prompt_tokens": 1000, "completion_tokens": 100, "total_tokens": 1100}
+        });
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(body.to_string())
+            .create_async()
+            .await;
+
+        let tracker = crate::cost::CostTracker::default();
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_cost_tracker(tracker.clone());
+        let input = InputObject::new(vec![Message::user("hi")]).with_tags(vec!["search".into()]);
+        Edgee::new(config).send("gpt-4o", input).await.unwrap();
+
+        let summary = tracker.tag("search").unwrap();
+        assert_eq!(summary.requests, 1);
+        assert_eq!(summary.usage.total_tokens, 1100);
+        assert!(summary.cost.total() > 0.0);
+        assert!(tracker.model("gpt-4o-2024-08-06").is_some());
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_cost_tracker_records_streams_once() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":1,",
+                "\"model\":\"gpt-4o\",\"choices\":[],",
+                "\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":5,\"total_tokens\":15}}\n\n",
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":1,",
+                "\"model\":\"gpt-4o\",\"choices\":[],",
+                "\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":20,\"total_tokens\":30}}\n\n",
+                "data: [DONE]\n\n"
+            ))
+            .create_async()
+            .await;
+
+        let tracker = crate::cost::CostTracker::default();
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_cost_tracker(tracker.clone());
+        let stream = Edgee::new(config).stream("gpt-4o", "hi").await.unwrap();
+        let chunks: Vec<_> = stream.collect().await;
+        assert_eq!(chunks.len(), 2);
+
+        let total = tracker.total();
+        assert_eq!(total.requests, 1);
+        assert_eq!(total.usage.total_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // Accept connections without ever answering
+        let _server = tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(format!("http://{}", address))
+            .with_timeout(std::time::Duration::from_millis(50))
+            .with_retry(RetryPolicy::none());
+        let err = Edgee::new(config).send("gpt-4o", "hi").await.unwrap_err();
+        assert!(matches!(err, Error::Timeout(_)));
+    }
+
+    #[cfg(feature = "stream")]
+    fn event(event: &str, data: &str) -> sse::SseEvent {
+        sse::SseEvent {
+            event: event.to_string(),
+            data: data.to_string(),
+            id: None,
+            retry: None,
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_parse_event_error_modes() {
+        let malformed = event("message", r#"{"id": "1", "choices": "#);
+
+        assert!(Edgee::parse_event(malformed.clone(), StreamErrorMode::Lenient).is_none());
+        match Edgee::parse_event(malformed, StreamErrorMode::Strict) {
+            Some(Err(Error::Stream { payload, .. })) => {
+                assert_eq!(payload.as_deref(), Some(r#"{"id": "1", "choices": "#));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        assert!(Edgee::parse_event(event("message", "[DONE]"), StreamErrorMode::Strict).is_none());
+        assert!(Edgee::parse_event(event("ping", "{}"), StreamErrorMode::Strict).is_none());
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_parse_event_in_band_errors() {
+        let data = r#"{"error": {"message": "Upstream provider failed", "type": "server_error"}}"#;
+        for mode in [StreamErrorMode::Lenient, StreamErrorMode::Strict] {
+            match Edgee::parse_event(event("message", data), mode) {
+                Some(Err(Error::Stream { message, payload })) => {
+                    assert_eq!(message, "Upstream provider failed");
+                    assert_eq!(payload.as_deref(), Some(data));
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+
+        match Edgee::parse_event(event("error", "overloaded"), StreamErrorMode::Lenient) {
+            Some(Err(Error::Stream { payload, .. })) => {
+                assert_eq!(payload.as_deref(), Some("overloaded"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // Some providers send `"error": null` on regular chunks
+        let data = r#"{"id": "1", "object": "chat.completion.chunk", "created": 0, "model": "gpt-4o",
+            "choices": [{"index": 0, "delta": {"content": "Hi"}}], "error": null}"#;
+        for mode in [StreamErrorMode::Lenient, StreamErrorMode::Strict] {
+            match Edgee::parse_event(event("message", data), mode) {
+                Some(Ok(chunk)) => assert_eq!(chunk.text(), Some("Hi")),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_request() {
+        let config = EdgeeConfig::new("test-key").with_default_header("x-team", "search");
+        let request = Edgee::build_request(
+            &config,
+            Method::POST,
+            CHAT_COMPLETIONS,
+            Some(json!({"model": "gpt-4o"})),
+        )
+        .unwrap();
+        assert_eq!(request.url, "https://api.edgee.ai/v1/chat/completions");
+        assert_eq!(request.headers["x-team"], "search");
+        assert_eq!(request.headers[header::CONTENT_TYPE], "application/json");
+        assert!(request.headers[header::AUTHORIZATION].is_sensitive());
+
+        let config = config.with_default_header("x-bad", "a\nb");
+        let err = Edgee::build_request(&config, Method::GET, "/v1/models", None).unwrap_err();
+        assert!(matches!(err, Error::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_build_body_with_sampling_parameters() {
+        let input = InputObject::new(vec![Message::user("hello")])
+            .with_temperature(0.2)
+            .with_top_p(0.9)
+            .with_max_completion_tokens(256)
+            .with_stop(vec!["END".to_string()])
+            .with_presence_penalty(0.5)
+            .with_frequency_penalty(-0.5)
+            .with_seed(42)
+            .with_n(2)
+            .with_logit_bias([(50256, -100)].into_iter().collect())
+            .with_user("user-123")
+            .with_reasoning_effort(ReasoningEffort::Low)
+            .with_compression(true);
+
+        for stream in [false, true] {
+            let body =
+                Edgee::build_body("gpt-4o".to_string(), input.clone().into(), stream).unwrap();
+            assert_eq!(
+                body,
+                json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "user", "content": "hello"}],
+                    "stream": stream,
+                    "temperature": 0.2,
+                    "top_p": 0.9,
+                    "max_completion_tokens": 256,
+                    "stop": ["END"],
+                    "presence_penalty": 0.5,
+                    "frequency_penalty": -0.5,
+                    "seed": 42,
+                    "n": 2,
+                    "logit_bias": {"50256": -100},
+                    "user": "user-123",
+                    "reasoning_effort": "low",
+                    "enable_compression": true,
+                })
+            );
+        }
+    }
 }
@@ -0,0 +1,599 @@
+use crate::{
+    client::Edgee,
+    error::Result,
+    models::{Content, InputObject, Message, Role, SendResponse, ToolChoice, Usage},
+    tokens,
+};
+#[cfg(feature = "stream")]
+use crate::{models::StreamChunk, stream::StreamAccumulator};
+#[cfg(feature = "stream")]
+use futures::stream::Stream;
+#[cfg(feature = "stream")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Instruction sent to the model when summarizing older turns
+const SUMMARY_PROMPT: &str = "Summarize the conversation above in a few sentences. \
+Keep every fact, decision and open question needed to continue it.";
+
+/// How the history is shortened when it exceeds the token budget
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    /// Drop the oldest turns until the history fits the budget
+    #[default]
+    DropOldest,
+    /// Keep only the last N messages
+    KeepLast(usize),
+    /// Replace older turns with a summary written by the model, keeping the last N messages
+    Summarize {
+        /// Number of recent messages kept verbatim
+        keep_last: usize,
+    },
+}
+
+/// Chat session keeping track of the message history
+///
+/// Replies from [`Conversation::send`] and [`Conversation::stream`] are
+/// appended to the history automatically. When a token budget is set, the
+/// history is truncated before each request according to the
+/// [`TruncationStrategy`]. Tool results always stay with the assistant
+/// message that requested them, and the system prompt is never dropped.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::{Conversation, Edgee, TruncationStrategy};
+///
+/// let client = Edgee::from_env()?;
+/// let mut conversation = Conversation::new(client, "gpt-4o")
+///     .with_system_prompt("You are a helpful assistant.")
+///     .with_token_budget(8_000)
+///     .with_truncation(TruncationStrategy::Summarize { keep_last: 6 });
+///
+/// conversation.send("My name is Alice.").await?;
+/// let response = conversation.send("What is my name?").await?;
+/// println!("{}", response.text().unwrap_or(""));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    client: Edgee,
+    model: String,
+    system: Option<Message>,
+    messages: Vec<Message>,
+    template: InputObject,
+    token_budget: Option<usize>,
+    truncation: TruncationStrategy,
+    usage: Usage,
+}
+
+impl Conversation {
+    /// Create an empty conversation with the given model
+    pub fn new(client: Edgee, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+            system: None,
+            messages: Vec::new(),
+            template: InputObject::new(Vec::new()),
+            token_budget: None,
+            truncation: TruncationStrategy::default(),
+            usage: Usage::default(),
+        }
+    }
+
+    /// Set the system prompt, sent first with every request
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system = Some(Message::system(prompt));
+        self
+    }
+
+    /// Set the request parameters (tools, sampling, tags...) used for every request
+    ///
+    /// The messages of the template are ignored.
+    pub fn with_template(mut self, template: InputObject) -> Self {
+        self.template = InputObject {
+            messages: Vec::new(),
+            ..template
+        };
+        self
+    }
+
+    /// Set the maximum number of tokens the history may use
+    pub fn with_token_budget(mut self, tokens: usize) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    /// Set how the history is shortened when it exceeds the token budget
+    pub fn with_truncation(mut self, truncation: TruncationStrategy) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    /// Messages of the history, without the system prompt
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// System prompt of the conversation
+    pub fn system_prompt(&self) -> Option<&str> {
+        self.system.as_ref().and_then(Message::text)
+    }
+
+    /// Token usage aggregated over all the requests of the conversation
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Estimated number of tokens used by the system prompt and the history
+    pub fn estimated_tokens(&self) -> usize {
+        tokens::count_message_tokens(&self.model, self.system.iter().chain(&self.messages))
+    }
+
+    /// Append a message to the history, such as a tool result
+    pub fn push(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+
+    /// Remove every message from the history, keeping the system prompt
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+
+    /// Send a user message and append the reply to the history
+    ///
+    /// If the request fails, the user message stays in the history and the
+    /// request can be retried with [`Conversation::complete`].
+    pub async fn send(&mut self, content: impl Into<Content>) -> Result<SendResponse> {
+        self.push(user_message(content.into()));
+        self.complete().await
+    }
+
+    /// Send the history as-is and append the reply to it
+    ///
+    /// Used to get the next reply after pushing tool results.
+    pub async fn complete(&mut self) -> Result<SendResponse> {
+        self.truncate().await?;
+        let response = self.client.send(self.model.clone(), self.input()).await?;
+        self.record(&response);
+        Ok(response)
+    }
+
+    /// Send a user message with streaming
+    ///
+    /// The reply is appended to the history once the stream has been fully
+    /// consumed. Nothing is appended if the stream is dropped early.
+    #[cfg(feature = "stream")]
+    pub async fn stream(&mut self, content: impl Into<Content>) -> Result<ConversationStream<'_>> {
+        self.push(user_message(content.into()));
+        self.truncate().await?;
+        let inner = self.client.stream(self.model.clone(), self.input()).await?;
+
+        Ok(ConversationStream {
+            conversation: self,
+            inner,
+            accumulator: StreamAccumulator::new(),
+            done: false,
+        })
+    }
+
+    /// Build the request for the current history
+    fn input(&self) -> InputObject {
+        InputObject {
+            messages: self.system.iter().chain(&self.messages).cloned().collect(),
+            ..self.template.clone()
+        }
+    }
+
+    /// Append the reply of a response to the history
+    fn record(&mut self, response: &SendResponse) {
+        if let Some(usage) = &response.usage {
+            self.usage += usage;
+        }
+        if let Some(message) = response.message() {
+            self.messages.push(message.clone());
+        }
+    }
+
+    /// Shorten the history if it exceeds the token budget
+    ///
+    /// Falls back to dropping the oldest turns when the strategy alone does
+    /// not bring the history under the budget. The last turn is never dropped.
+    async fn truncate(&mut self) -> Result<()> {
+        let Some(budget) = self.token_budget else {
+            return Ok(());
+        };
+        if self.estimated_tokens() <= budget {
+            return Ok(());
+        }
+
+        match self.truncation {
+            TruncationStrategy::DropOldest => {}
+            TruncationStrategy::KeepLast(n) => {
+                let start =
+                    turn_start(&self.messages, self.messages.len().saturating_sub(n.max(1)));
+                self.messages.drain(..start);
+            }
+            TruncationStrategy::Summarize { keep_last } => {
+                let start = turn_start(
+                    &self.messages,
+                    self.messages.len().saturating_sub(keep_last.max(1)),
+                );
+                if start > 0 {
+                    self.summarize(start).await?;
+                }
+            }
+        }
+
+        while self.estimated_tokens() > budget {
+            let start = next_turn(&self.messages);
+            if start >= self.messages.len() {
+                break;
+            }
+            self.messages.drain(..start);
+        }
+        Ok(())
+    }
+
+    /// Replace the messages before `end` with a summary written by the model
+    async fn summarize(&mut self, end: usize) -> Result<()> {
+        let mut messages: Vec<Message> = self.system.iter().cloned().collect();
+        messages.extend_from_slice(&self.messages[..end]);
+        messages.push(Message::user(SUMMARY_PROMPT));
+
+        // Keep the tags, and the tools the history may refer to, but ask for
+        // plain text
+        let input = InputObject {
+            messages,
+            tool_choice: self.template.tools.as_ref().map(|_| ToolChoice::None),
+            response_format: None,
+            ..self.template.clone()
+        };
+        let response = self.client.send(self.model.clone(), input).await?;
+        if let Some(usage) = &response.usage {
+            self.usage += usage;
+        }
+
+        let summary = response.text().unwrap_or_default();
+        self.messages.splice(
+            ..end,
+            [Message::system(format!(
+                "Summary of the earlier conversation:\n{}",
+                summary
+            ))],
+        );
+        Ok(())
+    }
+}
+
+/// Stream of a reply in a [`Conversation`]
+///
+/// Appends the reply to the history when the stream ends.
+#[cfg(feature = "stream")]
+pub struct ConversationStream<'a> {
+    conversation: &'a mut Conversation,
+    inner: Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>,
+    accumulator: StreamAccumulator,
+    done: bool,
+}
+
+#[cfg(feature = "stream")]
+impl Stream for ConversationStream<'_> {
+    type Item = Result<StreamChunk>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        let this = &mut *self;
+        match this.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.accumulator.push(&chunk);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(None) => {
+                this.done = true;
+                let response = std::mem::take(&mut this.accumulator).finish();
+                this.conversation.record(&response);
+                Poll::Ready(None)
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl std::fmt::Debug for ConversationStream<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConversationStream")
+            .field("accumulator", &self.accumulator)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Build a user message from any content
+fn user_message(content: Content) -> Message {
+    Message {
+        role: Role::User,
+        content: Some(content),
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
+/// Move `index` back to the start of its turn
+///
+/// Tool results cannot start a turn, as they must follow the assistant
+/// message holding the matching tool calls.
+fn turn_start(messages: &[Message], mut index: usize) -> usize {
+    while index > 0 && messages.get(index).is_some_and(|m| m.role == Role::Tool) {
+        index -= 1;
+    }
+    index
+}
+
+/// Index where the second turn starts, or the length of the history if there is a single turn
+fn next_turn(messages: &[Message]) -> usize {
+    (1..messages.len())
+        .find(|&i| messages[i].role != Role::Tool)
+        .unwrap_or(messages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        EdgeeConfig, FunctionCall, FunctionDefinition, JsonSchema, Tool, ToolCall,
+    };
+
+    fn response(content: &str) -> String {
+        serde_json::json!({
+            "id": "test-id",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": content},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+        })
+        .to_string()
+    }
+
+    fn conversation(url: &str) -> Conversation {
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(url));
+        Conversation::new(client, "gpt-4o")
+    }
+
+    fn tool_turn(id: &str) -> [Message; 2] {
+        let mut call = Message::assistant("");
+        call.content = None;
+        call.tool_calls = Some(vec![ToolCall {
+            id: id.to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: "lookup".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }]);
+        [call, Message::tool(id, "x".repeat(40))]
+    }
+
+    #[tokio::test]
+    async fn test_send_appends_reply() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "messages": [
+                    {"role": "system", "content": "Be brief."},
+                    {"role": "user", "content": "Hello"}
+                ],
+                "temperature": 0.2
+            })))
+            .with_body(response("Hi!"))
+            .create_async()
+            .await;
+
+        let mut conversation = conversation(&server.url())
+            .with_system_prompt("Be brief.")
+            .with_template(InputObject::new(vec![]).with_temperature(0.2));
+        conversation.send("Hello").await.unwrap();
+
+        let messages = conversation.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].role, Role::Assistant);
+        assert_eq!(messages[1].text(), Some("Hi!"));
+        assert_eq!(conversation.usage().total_tokens, 15);
+        mock.assert_async().await;
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_stream_appends_reply() {
+        use futures::StreamExt;
+
+        let mut server = mockito::Server::new_async().await;
+        let chunk = |content: &str| {
+            format!(
+                "data: {}\n\n",
+                serde_json::json!({
+                    "id": "test-id",
+                    "object": "chat.completion.chunk",
+                    "created": 1234567890,
+                    "model": "gpt-4o",
+                    "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": null}]
+                })
+            )
+        };
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(format!("{}{}data: [DONE]\n\n", chunk("Hel"), chunk("lo")))
+            .create_async()
+            .await;
+
+        let mut conversation = conversation(&server.url());
+        let mut stream = conversation.stream("Hi").await.unwrap();
+        while let Some(chunk) = stream.next().await {
+            chunk.unwrap();
+        }
+
+        assert_eq!(conversation.messages().len(), 2);
+        assert_eq!(conversation.messages()[1].text(), Some("Hello"));
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_keeps_tool_results_paired() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(response("Done"))
+            .create_async()
+            .await;
+
+        let mut conversation = conversation(&server.url()).with_token_budget(10);
+        for message in tool_turn("call_1") {
+            conversation.push(message);
+        }
+        conversation.send("Next").await.unwrap();
+
+        // Dropping the tool call alone would leave its result first
+        let messages = conversation.messages();
+        assert_eq!(messages[0].role, Role::User);
+        assert_eq!(messages[0].text(), Some("Next"));
+    }
+
+    #[tokio::test]
+    async fn test_keep_last() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(response("Done"))
+            .create_async()
+            .await;
+
+        let mut conversation = conversation(&server.url())
+            .with_system_prompt("Be brief.")
+            .with_token_budget(60)
+            .with_truncation(TruncationStrategy::KeepLast(2));
+        for i in 0..4 {
+            conversation.push(Message::user(format!("Question {}", i)));
+            conversation.push(Message::assistant(format!("Answer {}", i)));
+        }
+        for message in tool_turn("call_1") {
+            conversation.push(message);
+        }
+        conversation.send("Last").await.unwrap();
+
+        // The last 2 messages start with a tool result, so its call is kept too
+        let roles: Vec<_> = conversation
+            .messages()
+            .iter()
+            .map(|m| m.role.clone())
+            .collect();
+        assert_eq!(
+            roles,
+            [Role::Assistant, Role::Tool, Role::User, Role::Assistant]
+        );
+        assert_eq!(conversation.system_prompt(), Some("Be brief."));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_older_turns() {
+        let mut server = mockito::Server::new_async().await;
+        let summary = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                "Summarize the conversation".to_string(),
+            ))
+            .with_body(response("Alice likes tea."))
+            .expect(1)
+            .create_async()
+            .await;
+        let reply = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::Regex(
+                "Summary of the earlier conversation".to_string(),
+            ))
+            .with_body(response("Tea."))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut conversation = conversation(&server.url())
+            .with_token_budget(50)
+            .with_truncation(TruncationStrategy::Summarize { keep_last: 1 });
+        conversation.push(Message::user("I am Alice and I like tea. ".repeat(10)));
+        conversation.push(Message::assistant("Noted."));
+        conversation.send("What do I like?").await.unwrap();
+
+        let messages = conversation.messages();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, Role::System);
+        assert!(messages[0].text().unwrap().ends_with("Alice likes tea."));
+        assert_eq!(conversation.usage().total_tokens, 30);
+        summary.assert_async().await;
+        reply.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_summarize_keeps_tools_and_tags() {
+        let mut server = mockito::Server::new_async().await;
+        let summary = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::Regex("Summarize the conversation".to_string()),
+                mockito::Matcher::PartialJson(serde_json::json!({
+                    "tools": [{"type": "function", "function": {"name": "lookup"}}],
+                    "tool_choice": "none",
+                    "tags": ["support"]
+                })),
+            ]))
+            .with_body(response("Looked something up."))
+            .expect(1)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(response("Done"))
+            .create_async()
+            .await;
+
+        let template = InputObject::new(vec![])
+            .with_tags(vec!["support".into()])
+            .with_tools(vec![Tool::function(FunctionDefinition {
+                name: "lookup".to_string(),
+                description: None,
+                parameters: JsonSchema {
+                    schema_type: "object".to_string(),
+                    properties: None,
+                    required: None,
+                    description: None,
+                },
+            })]);
+        let mut conversation = conversation(&server.url())
+            .with_template(template)
+            .with_token_budget(50)
+            .with_truncation(TruncationStrategy::Summarize { keep_last: 1 });
+        conversation.push(Message::user("Find the order of Alice. ".repeat(10)));
+        for message in tool_turn("call_1") {
+            conversation.push(message);
+        }
+        conversation.send("Next").await.unwrap();
+
+        assert!(conversation.messages()[0]
+            .text()
+            .unwrap()
+            .ends_with("Looked something up."));
+        summary.assert_async().await;
+    }
+}
@@ -0,0 +1,526 @@
+use crate::{
+    error::Result,
+    models::{Compression, SendResponse, Usage},
+    tokens::{is_model_version, strip_provider},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Price of a model, in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// Price of a million prompt tokens
+    pub input: f64,
+    /// Price of a million completion tokens
+    pub output: f64,
+}
+
+impl ModelPrice {
+    /// Create a price from USD per million prompt and completion tokens
+    pub fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+}
+
+/// Default prices, in USD per million tokens
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    // OpenAI
+    ("gpt-5", 1.25, 10.0),
+    ("gpt-5-mini", 0.25, 2.0),
+    ("gpt-5-nano", 0.05, 0.4),
+    ("gpt-4.1", 2.0, 8.0),
+    ("gpt-4.1-mini", 0.4, 1.6),
+    ("gpt-4.1-nano", 0.1, 0.4),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("o1", 15.0, 60.0),
+    ("o1-mini", 1.1, 4.4),
+    ("o1-pro", 150.0, 600.0),
+    ("o3", 2.0, 8.0),
+    ("o3-mini", 1.1, 4.4),
+    ("o3-pro", 20.0, 80.0),
+    ("o4-mini", 1.1, 4.4),
+    // Anthropic
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    // Google
+    ("gemini-2.5-pro", 1.25, 10.0),
+    ("gemini-2.5-flash", 0.3, 2.5),
+    ("gemini-2.5-flash-lite", 0.1, 0.4),
+    ("gemini-2.0-flash", 0.1, 0.4),
+    // Mistral
+    ("mistral-large", 2.0, 6.0),
+    ("mistral-small", 0.1, 0.3),
+];
+
+/// Cost of one or more requests, in USD
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cost {
+    /// Cost of the prompt tokens
+    pub input: f64,
+    /// Cost of the completion tokens
+    pub output: f64,
+    /// Money saved by compressing the prompt
+    pub saved: f64,
+}
+
+impl Cost {
+    /// Total cost of the prompt and completion tokens
+    pub fn total(&self) -> f64 {
+        self.input + self.output
+    }
+}
+
+impl std::ops::AddAssign for Cost {
+    fn add_assign(&mut self, other: Cost) {
+        self.input += other.input;
+        self.output += other.output;
+        self.saved += other.saved;
+    }
+}
+
+/// Computes the cost of responses from a price table
+///
+/// Prices are looked up by exact model name first, then by the longest
+/// matching prefix followed by a date or version suffix only, so
+/// `gpt-4o-2024-08-06` uses the price of `gpt-4o` but `gpt-4o-audio` has no
+/// price. Provider prefixes such as `openai/` are ignored.
+///
+/// # Example
+/// ```
+/// use edgee::cost::{CostCalculator, ModelPrice};
+/// use edgee::Usage;
+///
+/// let calculator = CostCalculator::new().with_price("my-fine-tune", ModelPrice::new(3.0, 12.0));
+/// let usage = Usage {
+///     prompt_tokens: 1_000,
+///     completion_tokens: 500,
+///     total_tokens: 1_500,
+/// };
+///
+/// let cost = calculator.cost("my-fine-tune", &usage).unwrap();
+/// println!("${:.4}", cost.total());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CostCalculator {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl Default for CostCalculator {
+    fn default() -> Self {
+        Self {
+            prices: DEFAULT_PRICES
+                .iter()
+                .map(|&(model, input, output)| (model.to_string(), ModelPrice::new(input, output)))
+                .collect(),
+        }
+    }
+}
+
+impl CostCalculator {
+    /// Create a calculator with the default prices
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a calculator without any price
+    pub fn empty() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Load prices from a JSON object mapping model names to prices
+    ///
+    /// The prices are added to the defaults, replacing them for the same
+    /// model names:
+    ///
+    /// ```json
+    /// { "gpt-4o": { "input": 2.5, "output": 10.0 } }
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self> {
+        let prices: HashMap<String, ModelPrice> = serde_json::from_str(json)?;
+        Ok(Self::new().with_prices(prices))
+    }
+
+    /// Set the price of a model and of its dated versions
+    pub fn with_price(mut self, model: impl Into<String>, price: ModelPrice) -> Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    /// Set the prices of several models
+    pub fn with_prices(mut self, prices: impl IntoIterator<Item = (String, ModelPrice)>) -> Self {
+        self.prices.extend(prices);
+        self
+    }
+
+    /// Get the price of a model
+    pub fn price(&self, model: &str) -> Option<&ModelPrice> {
+        if let Some(price) = self.prices.get(model) {
+            return Some(price);
+        }
+        let model = strip_provider(model);
+        self.prices
+            .iter()
+            .filter(|(name, _)| is_model_version(model, name))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| price)
+    }
+
+    /// Compute the cost of the given usage, if the model has a price
+    pub fn cost(&self, model: &str, usage: &Usage) -> Option<Cost> {
+        let price = self.price(model)?;
+        Some(Cost {
+            input: price_of(usage.prompt_tokens, price.input),
+            output: price_of(usage.completion_tokens, price.output),
+            saved: 0.0,
+        })
+    }
+
+    /// Compute the money saved by compressing the prompt, if the model has a price
+    pub fn savings(&self, model: &str, compression: &Compression) -> Option<f64> {
+        let price = self.price(model)?;
+        Some(price_of(compression.saved_tokens, price.input))
+    }
+
+    /// Compute the cost of a response, including compression savings
+    pub fn response_cost(&self, response: &SendResponse) -> Option<Cost> {
+        let mut cost = self.cost(&response.model, response.usage.as_ref()?)?;
+        if let Some(compression) = &response.compression {
+            cost.saved = self.savings(&response.model, compression).unwrap_or(0.0);
+        }
+        Some(cost)
+    }
+}
+
+/// Price of a number of tokens, from a price per million tokens
+fn price_of(tokens: u32, per_million: f64) -> f64 {
+    f64::from(tokens) * per_million / 1_000_000.0
+}
+
+/// Token usage summed over many requests
+///
+/// Unlike [`Usage`], the counters are 64-bit so that long-lived trackers do
+/// not overflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+}
+
+impl std::ops::AddAssign<&Usage> for UsageTotals {
+    fn add_assign(&mut self, usage: &Usage) {
+        self.prompt_tokens = self
+            .prompt_tokens
+            .saturating_add(u64::from(usage.prompt_tokens));
+        self.completion_tokens = self
+            .completion_tokens
+            .saturating_add(u64::from(usage.completion_tokens));
+        self.total_tokens = self
+            .total_tokens
+            .saturating_add(u64::from(usage.total_tokens));
+    }
+}
+
+/// Aggregated usage and cost of a group of requests
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostSummary {
+    /// Number of requests
+    pub requests: u64,
+    /// Number of requests whose model has no price
+    pub unpriced_requests: u64,
+    /// Aggregated token usage
+    pub usage: UsageTotals,
+    /// Tokens removed by prompt compression
+    pub saved_tokens: u64,
+    /// Aggregated cost
+    pub cost: Cost,
+}
+
+/// Snapshot of a [`CostTracker`], serializable for export
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostReport {
+    /// All the recorded requests
+    pub total: CostSummary,
+    /// Requests grouped by model
+    pub by_model: HashMap<String, CostSummary>,
+    /// Requests grouped by tag (a request with several tags is counted in each)
+    pub by_tag: HashMap<String, CostSummary>,
+}
+
+/// Cumulative cost tracker, shared between clones
+///
+/// Attach it to a client with [`EdgeeConfig::with_cost_tracker`] to record
+/// every response, including streamed ones that report usage.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::{cost::CostTracker, Edgee, EdgeeConfig, InputObject, Message};
+///
+/// let tracker = CostTracker::default();
+/// let client = Edgee::new(EdgeeConfig::new("your-api-key").with_cost_tracker(tracker.clone()));
+///
+/// let input = InputObject::new(vec![Message::user("Hello")]).with_tags(vec!["search".to_string()]);
+/// client.send("gpt-4o", input).await?;
+///
+/// let report = tracker.report();
+/// println!("${:.4} spent on search", report.by_tag["search"].cost.total());
+/// println!("{}", serde_json::to_string_pretty(&report)?);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`EdgeeConfig::with_cost_tracker`]: crate::EdgeeConfig::with_cost_tracker
+#[derive(Debug, Clone, Default)]
+pub struct CostTracker {
+    calculator: Arc<CostCalculator>,
+    report: Arc<Mutex<CostReport>>,
+}
+
+impl CostTracker {
+    /// Create a tracker using the given prices
+    pub fn new(calculator: CostCalculator) -> Self {
+        Self {
+            calculator: Arc::new(calculator),
+            report: Arc::default(),
+        }
+    }
+
+    /// Calculator used to price the requests
+    pub fn calculator(&self) -> &CostCalculator {
+        &self.calculator
+    }
+
+    /// Record a response sent with the given tags
+    pub fn record(&self, response: &SendResponse, tags: &[String]) {
+        if let Some(usage) = &response.usage {
+            self.record_usage(&response.model, usage, response.compression.as_ref(), tags);
+        }
+    }
+
+    /// Record the usage of a request sent with the given tags
+    pub fn record_usage(
+        &self,
+        model: &str,
+        usage: &Usage,
+        compression: Option<&Compression>,
+        tags: &[String],
+    ) {
+        let cost = self.calculator.cost(model, usage).map(|mut cost| {
+            if let Some(compression) = compression {
+                cost.saved = self.calculator.savings(model, compression).unwrap_or(0.0);
+            }
+            cost
+        });
+        let saved_tokens = compression.map_or(0, |c| c.saved_tokens);
+
+        let update = |summary: &mut CostSummary| {
+            summary.requests += 1;
+            summary.usage += usage;
+            summary.saved_tokens = summary.saved_tokens.saturating_add(u64::from(saved_tokens));
+            match cost {
+                Some(cost) => summary.cost += cost,
+                None => summary.unpriced_requests += 1,
+            }
+        };
+
+        let mut report = self.lock();
+        update(&mut report.total);
+        update(report.by_model.entry(model.to_string()).or_default());
+        for tag in tags {
+            update(report.by_tag.entry(tag.clone()).or_default());
+        }
+    }
+
+    /// Aggregated usage and cost of all the recorded requests
+    pub fn total(&self) -> CostSummary {
+        self.lock().total.clone()
+    }
+
+    /// Aggregated usage and cost of the requests sent with the given tag
+    pub fn tag(&self, tag: &str) -> Option<CostSummary> {
+        self.lock().by_tag.get(tag).cloned()
+    }
+
+    /// Aggregated usage and cost of the requests sent to the given model
+    pub fn model(&self, model: &str) -> Option<CostSummary> {
+        self.lock().by_model.get(model).cloned()
+    }
+
+    /// Snapshot of everything recorded so far
+    pub fn report(&self) -> CostReport {
+        self.lock().clone()
+    }
+
+    /// Forget everything recorded so far
+    pub fn reset(&self) {
+        *self.lock() = CostReport::default();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CostReport> {
+        self.report.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Records the usage of a streamed request once, when the stream is dropped
+///
+/// Usage may be repeated on several chunks, so only the last one seen is
+/// recorded.
+#[cfg(feature = "stream")]
+pub(crate) struct StreamUsage {
+    tracker: CostTracker,
+    tags: Vec<String>,
+    last: Option<(String, Usage)>,
+    compression: Option<Compression>,
+}
+
+#[cfg(feature = "stream")]
+impl StreamUsage {
+    pub(crate) fn new(tracker: CostTracker, tags: Vec<String>) -> Self {
+        Self {
+            tracker,
+            tags,
+            last: None,
+            compression: None,
+        }
+    }
+
+    /// Remember the usage and compression carried by a chunk
+    pub(crate) fn observe(&mut self, chunk: &crate::models::StreamChunk) {
+        if let Some(usage) = &chunk.usage {
+            self.last = Some((chunk.model.clone(), usage.clone()));
+        }
+        if let Some(compression) = &chunk.compression {
+            self.compression = Some(compression.clone());
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl Drop for StreamUsage {
+    fn drop(&mut self) {
+        if let Some((model, usage)) = self.last.take() {
+            self.tracker
+                .record_usage(&model, &usage, self.compression.as_ref(), &self.tags);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[test]
+    fn test_price_lookup() {
+        let calculator = CostCalculator::new();
+        assert_eq!(calculator.price("gpt-4o").unwrap().input, 2.5);
+        assert_eq!(calculator.price("gpt-4o-2024-08-06").unwrap().input, 2.5);
+        assert_eq!(calculator.price("openai/gpt-4o-mini").unwrap().input, 0.15);
+        assert!(calculator.price("unknown-model").is_none());
+        assert_eq!(
+            calculator.price("claude-sonnet-4-20250514").unwrap().input,
+            3.0
+        );
+        assert_eq!(calculator.price("mistral-large-latest").unwrap().input, 2.0);
+        assert_eq!(calculator.price("gpt-3.5-turbo-0613").unwrap().input, 0.5);
+
+        // Variants are priced on their own, or not at all
+        assert_eq!(calculator.price("o1-mini").unwrap().input, 1.1);
+        assert_eq!(calculator.price("o1-mini-2024-09-12").unwrap().input, 1.1);
+        assert_eq!(calculator.price("o3-mini").unwrap().output, 4.4);
+        assert_eq!(calculator.price("o3-pro").unwrap().input, 20.0);
+        assert_eq!(
+            calculator.price("gemini-2.5-flash-lite").unwrap().input,
+            0.1
+        );
+        assert!(calculator.price("gpt-4o-audio-preview").is_none());
+        assert!(calculator.price("o1-ultra").is_none());
+
+        let calculator =
+            CostCalculator::from_json(r#"{"gpt-4o": {"input": 1.0, "output": 2.0}}"#).unwrap();
+        assert_eq!(calculator.price("gpt-4o").unwrap().output, 2.0);
+        assert_eq!(calculator.price("gpt-4o-mini").unwrap().output, 0.6);
+    }
+
+    #[test]
+    fn test_response_cost_with_compression() {
+        let calculator = CostCalculator::empty().with_price("model", ModelPrice::new(2.0, 8.0));
+        let response: SendResponse = serde_json::from_value(serde_json::json!({
+            "id": "test-id",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "model",
+            "choices": [],
+            "usage": {"prompt_tokens": 500_000, "completion_tokens": 250_000, "total_tokens": 750_000},
+            "compression": {"input_tokens": 1_000_000, "saved_tokens": 500_000, "rate": 0.5}
+        }))
+        .unwrap();
+
+        let cost = calculator.response_cost(&response).unwrap();
+        assert_eq!(cost.input, 1.0);
+        assert_eq!(cost.output, 2.0);
+        assert_eq!(cost.total(), 3.0);
+        assert_eq!(cost.saved, 1.0);
+    }
+
+    #[test]
+    fn test_tracker_groups_by_model_and_tag() {
+        let tracker = CostTracker::new(
+            CostCalculator::empty().with_price("model", ModelPrice::new(1.0, 1.0)),
+        );
+        let search = vec!["search".to_string()];
+        tracker.record_usage("model", &usage(1_000_000, 0), None, &search);
+        tracker.record_usage("model", &usage(0, 1_000_000), None, &[]);
+        tracker.record_usage("other", &usage(10, 10), None, &search);
+
+        let total = tracker.total();
+        assert_eq!(total.requests, 3);
+        assert_eq!(total.unpriced_requests, 1);
+        assert_eq!(total.cost.total(), 2.0);
+        assert_eq!(tracker.model("model").unwrap().requests, 2);
+        assert_eq!(tracker.tag("search").unwrap().usage.total_tokens, 1_000_020);
+
+        let report = serde_json::to_value(tracker.report()).unwrap();
+        assert_eq!(report["by_tag"]["search"]["requests"], 2);
+
+        tracker.reset();
+        assert_eq!(tracker.total(), CostSummary::default());
+    }
+
+    #[test]
+    fn test_tracker_totals_exceed_u32() {
+        let tracker = CostTracker::new(CostCalculator::empty());
+        let large = Usage {
+            prompt_tokens: u32::MAX - 1,
+            completion_tokens: 1,
+            total_tokens: u32::MAX,
+        };
+        tracker.record_usage("model", &large, None, &[]);
+        tracker.record_usage("model", &large, None, &[]);
+
+        let usage = tracker.total().usage;
+        assert_eq!(usage.prompt_tokens, 2 * u64::from(u32::MAX - 1));
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, 2 * u64::from(u32::MAX));
+    }
+}
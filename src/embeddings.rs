@@ -0,0 +1,354 @@
+use crate::{client::Edgee, error::Result, models::Usage, telemetry::RequestSpan};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+
+/// Path of the embeddings endpoint
+const EMBEDDINGS: &str = "/v1/embeddings";
+
+/// Text to embed, either a single string or a batch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Single(String),
+    Batch(Vec<String>),
+}
+
+impl From<String> for EmbeddingInput {
+    fn from(text: String) -> Self {
+        EmbeddingInput::Single(text)
+    }
+}
+
+impl From<&str> for EmbeddingInput {
+    fn from(text: &str) -> Self {
+        EmbeddingInput::Single(text.to_string())
+    }
+}
+
+impl From<Vec<String>> for EmbeddingInput {
+    fn from(texts: Vec<String>) -> Self {
+        EmbeddingInput::Batch(texts)
+    }
+}
+
+impl From<Vec<&str>> for EmbeddingInput {
+    fn from(texts: Vec<&str>) -> Self {
+        EmbeddingInput::Batch(texts.into_iter().map(str::to_string).collect())
+    }
+}
+
+/// Encoding of the vectors on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingFormat {
+    /// JSON arrays of floats
+    Float,
+    /// Base64 strings of little-endian `f32`, smaller to transfer
+    Base64,
+}
+
+/// Embeddings request
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingRequest {
+    pub input: EmbeddingInput,
+    /// Number of dimensions of the vectors, for models supporting it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_format: Option<EncodingFormat>,
+    /// Identifier of the end user, for abuse monitoring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl EmbeddingRequest {
+    /// Create a request for the given input
+    pub fn new(input: impl Into<EmbeddingInput>) -> Self {
+        Self {
+            input: input.into(),
+            dimensions: None,
+            encoding_format: None,
+            user: None,
+        }
+    }
+
+    /// Set the number of dimensions of the vectors
+    pub fn with_dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Set the encoding of the vectors on the wire
+    ///
+    /// Vectors are always decoded to `Vec<f32>`.
+    pub fn with_encoding_format(mut self, encoding_format: EncodingFormat) -> Self {
+        self.encoding_format = Some(encoding_format);
+        self
+    }
+
+    /// Set the identifier of the end user
+    pub fn with_user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+}
+
+impl<T: Into<EmbeddingInput>> From<T> for EmbeddingRequest {
+    fn from(input: T) -> Self {
+        Self::new(input)
+    }
+}
+
+/// Token usage of an embeddings request
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+/// Embedding of a single input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Embedding {
+    /// Position of the input in the batch
+    pub index: u32,
+    #[serde(deserialize_with = "deserialize_vector")]
+    pub embedding: Vec<f32>,
+}
+
+/// Response from an embeddings request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    pub model: String,
+    pub data: Vec<Embedding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<EmbeddingUsage>,
+}
+
+impl EmbeddingResponse {
+    /// Get the vector of the first input
+    pub fn embedding(&self) -> Option<&[f32]> {
+        self.data
+            .iter()
+            .find(|e| e.index == 0)
+            .map(|e| e.embedding.as_slice())
+    }
+
+    /// Get the vectors in the order of the inputs
+    pub fn into_vectors(mut self) -> Vec<Vec<f32>> {
+        self.data.sort_by_key(|e| e.index);
+        self.data.into_iter().map(|e| e.embedding).collect()
+    }
+}
+
+impl Edgee {
+    /// Create embeddings for one or more texts
+    ///
+    /// # Arguments
+    /// * `model` - The embedding model to use (e.g., "text-embedding-3-small")
+    /// * `input` - A string, a batch of strings or an [`EmbeddingRequest`]
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use edgee::embeddings::{cosine_similarity, EmbeddingRequest, EncodingFormat};
+    /// use edgee::Edgee;
+    ///
+    /// let client = Edgee::from_env()?;
+    /// let request = EmbeddingRequest::new(vec!["The cat sleeps", "A kitten naps"])
+    ///     .with_dimensions(256)
+    ///     .with_encoding_format(EncodingFormat::Base64);
+    /// let vectors = client
+    ///     .embed("text-embedding-3-small", request)
+    ///     .await?
+    ///     .into_vectors();
+    ///
+    /// println!("{}", cosine_similarity(&vectors[0], &vectors[1]));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn embed(
+        &self,
+        model: impl Into<String>,
+        input: impl Into<EmbeddingRequest>,
+    ) -> Result<EmbeddingResponse> {
+        let model = model.into();
+        let telemetry = RequestSpan::new("embeddings", &model, &[], false);
+        let mut body = serde_json::to_value(input.into())?;
+        body["model"] = json!(model);
+
+        let response: EmbeddingResponse = telemetry
+            .instrument(self.post_json(EMBEDDINGS, &body))
+            .await
+            .map_err(|e| telemetry.record_error(e))?;
+        if let Some(usage) = &response.usage {
+            let usage = Usage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: 0,
+                total_tokens: usage.total_tokens,
+            };
+            telemetry.record_usage(&usage, None);
+            if let Some(tracker) = &self.config().cost_tracker {
+                tracker.record_usage(&response.model, &usage, None, &[]);
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// Cosine similarity of two vectors, between -1.0 and 1.0
+///
+/// Returns 0.0 when the vectors have different lengths or one of them is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// Rank candidates by cosine similarity to a query
+///
+/// Returns the indices and similarities of the `k` most similar candidates,
+/// most similar first.
+pub fn top_k<V: AsRef<[f32]>>(query: &[f32], candidates: &[V], k: usize) -> Vec<(usize, f32)> {
+    let mut scores: Vec<(usize, f32)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| (i, cosine_similarity(query, candidate.as_ref())))
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores.truncate(k);
+    scores
+}
+
+/// Deserialize a vector sent either as floats or as base64
+fn deserialize_vector<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<f32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Vector {
+        Float(Vec<f32>),
+        Base64(String),
+    }
+
+    match Vector::deserialize(deserializer)? {
+        Vector::Float(vector) => Ok(vector),
+        Vector::Base64(data) => {
+            let bytes = BASE64.decode(data).map_err(serde::de::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(serde::de::Error::custom(
+                    "base64 embedding length is not a multiple of 4 bytes",
+                ));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EdgeeConfig;
+
+    #[test]
+    fn test_request_serialization() {
+        let request = EmbeddingRequest::new("hello");
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"input": "hello"})
+        );
+
+        let request = EmbeddingRequest::new(vec!["a", "b"])
+            .with_dimensions(256)
+            .with_encoding_format(EncodingFormat::Base64);
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"input": ["a", "b"], "dimensions": 256, "encoding_format": "base64"})
+        );
+    }
+
+    #[test]
+    fn test_base64_vectors() {
+        let bytes: Vec<u8> = [1.0f32, -0.5, 0.25]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let response: EmbeddingResponse = serde_json::from_value(json!({
+            "object": "list",
+            "model": "text-embedding-3-small",
+            "data": [
+                {"object": "embedding", "index": 1, "embedding": [0.5, 0.5]},
+                {"object": "embedding", "index": 0, "embedding": BASE64.encode(bytes)}
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(response.embedding(), Some([1.0, -0.5, 0.25].as_slice()));
+        assert_eq!(
+            response.into_vectors(),
+            vec![vec![1.0, -0.5, 0.25], vec![0.5, 0.5]]
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+
+        let candidates = vec![vec![0.0, 1.0], vec![1.0, 0.1], vec![-1.0, 0.0]];
+        let ranked = top_k(&[1.0, 0.0], &candidates, 2);
+        assert_eq!(ranked.iter().map(|r| r.0).collect::<Vec<_>>(), [1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/embeddings")
+            .match_body(mockito::Matcher::Json(json!({
+                "model": "text-embedding-3-small",
+                "input": ["a", "b"]
+            })))
+            .with_body(
+                json!({
+                    "object": "list",
+                    "model": "text-embedding-3-small",
+                    "data": [
+                        {"object": "embedding", "index": 0, "embedding": [0.1, 0.2]},
+                        {"object": "embedding", "index": 1, "embedding": [0.3, 0.4]}
+                    ],
+                    "usage": {"prompt_tokens": 2, "total_tokens": 2}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let response = client
+            .embed("text-embedding-3-small", vec!["a", "b"])
+            .await
+            .unwrap();
+
+        assert_eq!(response.data.len(), 2);
+        assert_eq!(response.usage.unwrap().total_tokens, 2);
+        mock.assert_async().await;
+    }
+}
@@ -1,9 +1,16 @@
+use std::fmt;
+
 /// Errors that can occur when using the Edgee SDK
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     /// HTTP request failed
     #[error("HTTP request failed: {0}")]
-    Http(#[from] reqwest::Error),
+    Http(reqwest::Error),
+
+    /// HTTP request timed out
+    #[error("Request timed out: {0}")]
+    Timeout(reqwest::Error),
 
     /// JSON serialization/deserialization failed
     #[error("JSON error: {0}")]
@@ -14,17 +21,297 @@ pub enum Error {
     MissingApiKey,
 
     /// API returned an error
-    #[error("API error (status {status}): {message}")]
-    Api { status: u16, message: String },
+    #[error("{0}")]
+    Api(Box<ApiError>),
+
+    /// Streaming error: a chunk could not be parsed or the gateway sent an
+    /// error in the middle of the stream
+    #[error("Streaming error: {message}")]
+    Stream {
+        message: String,
+        /// Raw event data that caused the error, if any
+        payload: Option<String>,
+    },
+
+    /// The model reply does not match the expected structure
+    #[error("Invalid structured output: {message}")]
+    StructuredOutput {
+        message: String,
+        /// Raw text content of the reply, if any
+        content: Option<String>,
+    },
+
+    /// The arguments of a tool call do not match the expected type
+    #[error("Invalid arguments for tool `{name}`: {source}")]
+    ToolArguments {
+        name: String,
+        source: serde_json::Error,
+    },
+
+    /// The gateway returned a response without any choice
+    #[error("The response has no choices")]
+    EmptyResponse(Box<crate::models::SendResponse>),
 
-    /// Streaming error
-    #[error("Streaming error: {0}")]
-    Stream(String),
+    /// A tool loop reached its iteration limit without a final answer
+    #[error("No final answer after {limit} iterations")]
+    MaxIterations {
+        limit: usize,
+        /// Transcript so far, including the tool calls and tool results
+        messages: Vec<crate::models::Message>,
+        /// Token usage aggregated over all the requests
+        usage: crate::models::Usage,
+    },
 
     /// Invalid configuration
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
 }
 
+impl Error {
+    /// Get the API error details, if this is an API error
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Get the HTTP status returned by the gateway, if any
+    pub fn status(&self) -> Option<u16> {
+        self.api_error().map(|e| e.status)
+    }
+
+    /// Whether the request was rejected because of a rate limit
+    pub fn is_rate_limited(&self) -> bool {
+        self.api_error().is_some_and(ApiError::is_rate_limited)
+    }
+
+    /// Whether the request was rejected because of missing or invalid credentials
+    pub fn is_auth_error(&self) -> bool {
+        match self {
+            Error::MissingApiKey => true,
+            Error::Api(error) => error.is_auth_error(),
+            _ => false,
+        }
+    }
+
+    /// Whether the prompt does not fit in the context window of the model
+    pub fn is_context_length_exceeded(&self) -> bool {
+        self.api_error()
+            .is_some_and(ApiError::is_context_length_exceeded)
+    }
+
+    /// Whether sending the same request again may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout(_) => true,
+            Error::Http(error) => error.is_connect() || error.is_timeout(),
+            Error::Api(error) => error.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Error::Timeout(error)
+        } else {
+            Error::Http(error)
+        }
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(error: ApiError) -> Self {
+        Error::Api(Box::new(error))
+    }
+}
+
+/// Error returned by the gateway
+///
+/// The fields are parsed from the OpenAI-compatible error envelope
+/// (`{"error": {"message", "type", "code", "param"}}`) when the body
+/// contains one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    /// HTTP status of the response
+    pub status: u16,
+    /// Human-readable error message (the raw body if it is not a JSON envelope)
+    pub message: String,
+    /// Error type, e.g. `invalid_request_error`
+    pub error_type: Option<String>,
+    /// Machine-readable error code, e.g. `context_length_exceeded`
+    pub code: Option<String>,
+    /// Request parameter the error relates to
+    pub param: Option<String>,
+    /// Value of the `x-request-id` response header
+    pub request_id: Option<String>,
+    /// Raw response body
+    pub body: String,
+}
+
+impl ApiError {
+    /// Build an error from a response status and body
+    pub fn from_body(status: u16, body: impl Into<String>) -> Self {
+        let body = body.into();
+        let envelope = serde_json::from_str::<serde_json::Value>(&body).ok();
+        let error = envelope
+            .as_ref()
+            .map(|value| value.get("error").unwrap_or(value));
+        let field = |name: &str| {
+            error
+                .and_then(|e| e.get(name))
+                .and_then(|value| match value {
+                    serde_json::Value::String(s) => Some(s.clone()),
+                    serde_json::Value::Number(n) => Some(n.to_string()),
+                    _ => None,
+                })
+        };
+
+        let message = error
+            .and_then(|e| e.as_str().map(str::to_string))
+            .or_else(|| field("message"))
+            .unwrap_or_else(|| {
+                if body.is_empty() {
+                    "Unknown error".to_string()
+                } else {
+                    body.clone()
+                }
+            });
+
+        Self {
+            status,
+            message,
+            error_type: field("type"),
+            code: field("code"),
+            param: field("param"),
+            request_id: None,
+            body,
+        }
+    }
+
+    /// Set the request ID
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Whether the request was rejected because of a rate limit
+    pub fn is_rate_limited(&self) -> bool {
+        self.status == 429 || self.has_kind(&["rate_limit_exceeded", "rate_limit_error"])
+    }
+
+    /// Whether the request was rejected because of missing or invalid credentials
+    pub fn is_auth_error(&self) -> bool {
+        matches!(self.status, 401 | 403)
+            || self.has_kind(&[
+                "authentication_error",
+                "invalid_api_key",
+                "permission_error",
+            ])
+    }
+
+    /// Whether the prompt does not fit in the context window of the model
+    pub fn is_context_length_exceeded(&self) -> bool {
+        if self.has_kind(&["context_length_exceeded", "string_above_max_length"]) {
+            return true;
+        }
+        // Some providers only describe the error in the message
+        let message = self.message.to_lowercase();
+        message.contains("context length") || message.contains("context window")
+    }
+
+    /// Whether sending the same request again may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.status, 408 | 409 | 429) || self.status >= 500
+    }
+
+    /// Whether the error type or code is one of the given values
+    fn has_kind(&self, kinds: &[&str]) -> bool {
+        [&self.error_type, &self.code]
+            .into_iter()
+            .flatten()
+            .any(|kind| kinds.contains(&kind.as_str()))
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "API error (status {}", self.status)?;
+        if let Some(code) = &self.code {
+            write!(f, ", code {}", code)?;
+        }
+        write!(f, "): {}", self.message)?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request ID: {})", request_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
 /// Result type alias for Edgee operations
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_envelope() {
+        let error = ApiError::from_body(
+            400,
+            r#"{"error": {"message": "This model's maximum context length is 128000 tokens", "type": "invalid_request_error", "code": "context_length_exceeded", "param": "messages"}}"#,
+        )
+        .with_request_id("req_123");
+
+        assert_eq!(
+            error.message,
+            "This model's maximum context length is 128000 tokens"
+        );
+        assert_eq!(error.error_type.as_deref(), Some("invalid_request_error"));
+        assert_eq!(error.code.as_deref(), Some("context_length_exceeded"));
+        assert_eq!(error.param.as_deref(), Some("messages"));
+        assert_eq!(
+            error.to_string(),
+            "API error (status 400, code context_length_exceeded): This model's maximum context length is 128000 tokens (request ID: req_123)"
+        );
+
+        let error = Error::from(error);
+        assert!(error.is_context_length_exceeded());
+        assert!(!error.is_rate_limited());
+        assert!(!error.is_retryable());
+        assert_eq!(error.status(), Some(400));
+    }
+
+    #[test]
+    fn test_parse_non_json_body() {
+        let error = ApiError::from_body(502, "Bad Gateway");
+        assert_eq!(error.message, "Bad Gateway");
+        assert_eq!(error.code, None);
+        assert!(error.is_retryable());
+
+        assert_eq!(ApiError::from_body(500, "").message, "Unknown error");
+    }
+
+    #[test]
+    fn test_error_predicates() {
+        let rate_limited = Error::from(ApiError::from_body(
+            429,
+            r#"{"error": {"message": "Slow down", "code": 429}}"#,
+        ));
+        assert!(rate_limited.is_rate_limited());
+        assert!(rate_limited.is_retryable());
+        assert_eq!(
+            rate_limited.api_error().unwrap().code.as_deref(),
+            Some("429")
+        );
+
+        let unauthorized = Error::from(ApiError::from_body(401, r#"{"error": "Invalid API key"}"#));
+        assert!(unauthorized.is_auth_error());
+        assert_eq!(unauthorized.api_error().unwrap().message, "Invalid API key");
+        assert!(Error::MissingApiKey.is_auth_error());
+    }
+}
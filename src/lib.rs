@@ -9,8 +9,9 @@
 //!
 //! - **Async/await support** - Built on tokio for efficient async operations
 //! - **Type-safe** - Strong typing with Rust enums and structs
-//! - **Streaming** - Full support for streaming responses
-//! - **Tool calling** - Support for function/tool calling
+//! - **Streaming** - Full support for streaming responses (`stream` feature)
+//! - **Tool calling** - Support for function/tool calling, with an automatic
+//!   tool runner (`tools` feature)
 //! - **Flexible input** - Accept strings, message arrays, or structured objects
 //! - **Error handling** - Comprehensive error types with `thiserror`
 //! - **Zero-cost abstractions** - Efficient implementation with minimal overhead
@@ -39,6 +40,7 @@
 //! use edgee::Edgee;
 //! use tokio_stream::StreamExt;
 //!
+//! # #[cfg(feature = "stream")]
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let client = Edgee::from_env()?;
@@ -55,6 +57,8 @@
 //!
 //!     Ok(())
 //! }
+//! # #[cfg(not(feature = "stream"))]
+//! # fn main() {}
 //! ```
 //!
 //! ## Tool Calling Example
@@ -101,14 +105,48 @@
 //! }
 //! ```
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod catalog;
 pub mod client;
+pub mod conversation;
+pub mod cost;
+pub mod embeddings;
 pub mod error;
+pub mod middleware;
 pub mod models;
+pub mod retry;
+#[cfg(feature = "stream")]
+pub mod sse;
+#[cfg(feature = "stream")]
+pub mod stream;
+mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tokens;
+#[cfg(feature = "tools")]
+pub mod tools;
+
+#[cfg(feature = "schemars")]
+pub use schemars;
 
 // Re-export main types for convenience
+pub use catalog::Model;
 pub use client::{Edgee, Input};
-pub use error::{Error, Result};
+#[cfg(feature = "stream")]
+pub use conversation::ConversationStream;
+pub use conversation::{Conversation, TruncationStrategy};
+pub use error::{ApiError, Error, Result};
 pub use models::*;
+pub use retry::RetryPolicy;
+#[cfg(feature = "stream")]
+pub use stream::{ResponseStreamExt, StreamAccumulator, ToolCallAccumulator};
+#[cfg(all(feature = "tools", feature = "schemars"))]
+pub use tools::EdgeeTool;
+#[cfg(feature = "tools")]
+pub use tools::{ToolRunOutput, ToolRunner};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,538 @@
+//! Middleware around the HTTP requests sent to the gateway
+//!
+//! Every request of the async client goes through a chain of [`Middleware`]
+//! before reaching the network. Each middleware receives the outgoing
+//! [`Request`] and a [`Next`] handle to call the rest of the chain, so it can
+//! mutate the request, observe or replace the [`Response`], or answer
+//! without calling the gateway at all.
+//!
+//! Middleware registered with [`EdgeeConfig::with_middleware`] run in
+//! registration order, around the built-in ones: retries (configured with
+//! [`EdgeeConfig::retry`]) then, with the `tracing` feature, logging of every
+//! HTTP exchange. A registered middleware therefore sees a single call even
+//! when the request is retried.
+//!
+//! Non-success responses are returned as [`Response`]s and only converted
+//! into errors after the chain, so middleware can inspect them.
+//!
+//! [`EdgeeConfig::with_middleware`]: crate::EdgeeConfig::with_middleware
+//! [`EdgeeConfig::retry`]: crate::EdgeeConfig::retry
+
+#[cfg(feature = "tracing")]
+use crate::telemetry;
+use crate::{
+    error::{Error, Result},
+    retry::{Failure, RetryPolicy},
+};
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use http::Extensions;
+use reqwest::{header::HeaderMap, Method};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Boxed future returned by middleware
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Request or response processing step
+///
+/// # Example
+/// ```
+/// use edgee::middleware::{BoxFuture, Middleware, Next, Request, Response};
+/// use edgee::{EdgeeConfig, Result};
+/// use std::time::Instant;
+///
+/// /// Tag every request with a team header and log the latency
+/// struct Team(&'static str);
+///
+/// impl Middleware for Team {
+///     fn handle<'a>(&'a self, mut request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+///         Box::pin(async move {
+///             request.headers.insert("x-team", self.0.parse().unwrap());
+///             let start = Instant::now();
+///             let response = next.run(request).await;
+///             println!("gateway answered in {:?}", start.elapsed());
+///             response
+///         })
+///     }
+/// }
+///
+/// let config = EdgeeConfig::new("your-api-key").with_middleware(Team("search"));
+/// ```
+pub trait Middleware: Send + Sync + 'static {
+    /// Handle a request, calling `next` to forward it to the rest of the chain
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>>;
+
+    /// Name of the middleware, used in debug output
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+impl std::fmt::Debug for dyn Middleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Remaining middleware of the chain, ending with the HTTP client
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    client: &'a reqwest::Client,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middlewares: &'a [Arc<dyn Middleware>], client: &'a reqwest::Client) -> Self {
+        Self {
+            middlewares,
+            client,
+        }
+    }
+
+    /// Forward the request to the rest of the chain
+    ///
+    /// `Next` is `Copy`, so it can be run several times, e.g. to retry.
+    pub fn run(self, request: Request) -> BoxFuture<'a, Result<Response>> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => middleware.handle(
+                request,
+                Next {
+                    middlewares: rest,
+                    ..self
+                },
+            ),
+            None => Box::pin(send(self.client, request)),
+        }
+    }
+}
+
+impl std::fmt::Debug for Next<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Next")
+            .field("middlewares", &self.middlewares)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Outgoing HTTP request
+///
+/// The headers include the authentication, User-Agent and default headers
+/// of the configuration.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    /// Full URL, including the base URL of the configuration
+    pub url: String,
+    pub headers: HeaderMap,
+    /// JSON body, sent with `Content-Type: application/json`
+    pub body: Option<serde_json::Value>,
+    /// Total timeout of the request
+    pub timeout: Option<Duration>,
+    /// Typed values passed between middleware
+    pub extensions: Extensions,
+}
+
+impl Request {
+    /// Create a request without headers nor body
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: None,
+            timeout: None,
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Set the JSON body
+    pub fn with_body(mut self, body: serde_json::Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Path of the URL (e.g. `/v1/chat/completions`)
+    pub fn path(&self) -> &str {
+        let after_scheme = self
+            .url
+            .split_once("://")
+            .map_or(&*self.url, |(_, rest)| rest);
+        let path = after_scheme.find('/').map_or("/", |i| &after_scheme[i..]);
+        path.split(['?', '#']).next().unwrap_or(path)
+    }
+}
+
+/// Incoming HTTP response
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Body,
+    /// Typed values passed between middleware
+    pub extensions: Extensions,
+}
+
+impl Response {
+    /// Create a response without headers
+    pub fn new(status: u16, body: impl Into<Body>) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: body.into(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Whether the status is 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Body of a response, received chunk by chunk
+pub struct Body {
+    inner: Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>,
+}
+
+impl Body {
+    /// Create an empty body
+    pub fn empty() -> Self {
+        Self::from_stream(futures::stream::empty())
+    }
+
+    /// Create a body from a stream of chunks
+    pub fn from_stream(stream: impl Stream<Item = Result<Bytes>> + Send + 'static) -> Self {
+        Self {
+            inner: Box::pin(stream),
+        }
+    }
+
+    /// Read the whole body
+    pub async fn bytes(mut self) -> Result<Bytes> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = self.next().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok(bytes.into())
+    }
+}
+
+impl Stream for Body {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Self::from_stream(futures::stream::once(async move { Ok(bytes) }))
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes::from(bytes).into()
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Bytes::from(text).into()
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(text: &'static str) -> Self {
+        Bytes::from_static(text.as_bytes()).into()
+    }
+}
+
+impl std::fmt::Debug for Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Body").finish_non_exhaustive()
+    }
+}
+
+/// Send a request over the network
+async fn send(client: &reqwest::Client, request: Request) -> Result<Response> {
+    let mut builder = client
+        .request(request.method, request.url)
+        .headers(request.headers);
+    if let Some(timeout) = request.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(body) = &request.body {
+        builder = builder.json(body);
+    }
+
+    let response = builder.send().await?;
+    Ok(Response {
+        status: response.status().as_u16(),
+        headers: response.headers().clone(),
+        body: Body::from_stream(body_stream(response)),
+        extensions: Extensions::new(),
+    })
+}
+
+/// Stream the body of a response chunk by chunk
+fn body_stream(response: reqwest::Response) -> impl Stream<Item = Result<Bytes>> + Send {
+    futures::stream::unfold(Some(response), |response| async move {
+        let mut response = response?;
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(response))),
+            Ok(None) => None,
+            Err(e) => Some((Err(e.into()), None)),
+        }
+    })
+}
+
+/// Retries failed requests according to a [`RetryPolicy`]
+///
+/// The first body chunk is awaited before returning, so that failures
+/// happening before any byte was received can still be retried.
+pub(crate) struct Retry(pub(crate) RetryPolicy);
+
+impl Middleware for Retry {
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let policy = &self.0;
+            let mut attempt = 1;
+
+            loop {
+                let delay = match next.run(request.clone()).await {
+                    Ok(mut response) if response.is_success() => {
+                        let first = response.body.next().await;
+                        // The connection may drop before anything was yielded
+                        let delay = match &first {
+                            Some(Err(e)) => transport_error(e).and_then(|error| {
+                                policy.retry_delay(attempt, Failure::Body(error))
+                            }),
+                            _ => None,
+                        };
+                        match delay {
+                            Some(delay) => delay,
+                            None => {
+                                let rest = std::mem::replace(&mut response.body, Body::empty());
+                                response.body =
+                                    Body::from_stream(futures::stream::iter(first).chain(rest));
+                                return Ok(response);
+                            }
+                        }
+                    }
+                    Ok(response) => {
+                        let failure = Failure::Status(response.status, &response.headers);
+                        match policy.retry_delay(attempt, failure) {
+                            Some(delay) => delay,
+                            None => return Ok(response),
+                        }
+                    }
+                    Err(e) => {
+                        let delay = transport_error(&e).and_then(|error| {
+                            policy.retry_delay(attempt, Failure::Transport(error))
+                        });
+                        match delay {
+                            Some(delay) => delay,
+                            None => return Err(e),
+                        }
+                    }
+                };
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// Underlying HTTP client error, if any
+fn transport_error(error: &Error) -> Option<&reqwest::Error> {
+    match error {
+        Error::Http(error) | Error::Timeout(error) => Some(error),
+        _ => None,
+    }
+}
+
+/// Logs every HTTP exchange and records its status in the request span
+#[cfg(feature = "tracing")]
+pub(crate) struct Logging;
+
+#[cfg(feature = "tracing")]
+impl Middleware for Logging {
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let method = request.method.clone();
+            let path = request.path().to_string();
+            let start = std::time::Instant::now();
+
+            let result = next.run(request).await;
+            let status = result.as_ref().ok().map(|response| response.status);
+            if let Some(status) = status {
+                telemetry::record_status(status);
+            }
+            telemetry::exchange(&method, &path, status, start.elapsed());
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Edgee, EdgeeConfig, RetryPolicy};
+    use reqwest::header::{HeaderName, HeaderValue};
+    use std::sync::Mutex;
+
+    const COMPLETION: &str = r#"{"id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hello"}, "finish_reason": "stop"}]}"#;
+
+    /// Records the order in which middleware are called
+    struct Trace(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl Middleware for Trace {
+        fn handle<'a>(
+            &'a self,
+            mut request: Request,
+            next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response>> {
+            Box::pin(async move {
+                self.1.lock().unwrap().push(format!("> {}", self.0));
+                let name = HeaderName::try_from(format!("x-{}", self.0)).unwrap();
+                request.headers.insert(name, HeaderValue::from_static("1"));
+                let response = next.run(request).await;
+                let status = response.as_ref().map_or(0, |r| r.status);
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push(format!("< {} {}", self.0, status));
+                response
+            })
+        }
+    }
+
+    #[test]
+    fn test_request_path() {
+        let path = |url| Request::new(Method::GET, url).path().to_string();
+        assert_eq!(path("https://api.edgee.ai/v1/models?limit=1"), "/v1/models");
+        assert_eq!(path("http://127.0.0.1:8080"), "/");
+    }
+
+    #[tokio::test]
+    async fn test_middleware_order_around_retries() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("x-outer", "1")
+            .match_header("x-inner", "1")
+            .match_header("authorization", "Bearer test-key")
+            .with_body(COMPLETION)
+            .create_async()
+            .await;
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_retry(RetryPolicy::new().with_base_delay(Duration::from_millis(1)))
+            .with_middleware(Trace("outer", calls.clone()))
+            .with_middleware(Trace("inner", calls.clone()));
+        let response = Edgee::new(config).send("gpt-4o", "Hi").await.unwrap();
+
+        assert_eq!(response.text(), Some("Hello"));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["> outer", "> inner", "< inner 200", "< outer 200"]
+        );
+        unavailable.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_middleware_can_answer_and_rewrite_body() {
+        /// Answers every request locally with the prompt it received
+        struct Echo;
+
+        impl Middleware for Echo {
+            fn handle<'a>(
+                &'a self,
+                request: Request,
+                _next: Next<'a>,
+            ) -> BoxFuture<'a, Result<Response>> {
+                Box::pin(async move {
+                    let body = request.body.unwrap();
+                    let reply = serde_json::json!({
+                        "id": "local", "object": "chat.completion", "created": 0,
+                        "model": body["model"],
+                        "choices": [{"index": 0, "finish_reason": "stop",
+                            "message": {"role": "assistant", "content": body["messages"][0]["content"]}}]
+                    });
+                    Ok(Response::new(200, reply.to_string()))
+                })
+            }
+        }
+
+        /// Redacts the prompts before they leave the process
+        struct Redact;
+
+        impl Middleware for Redact {
+            fn handle<'a>(
+                &'a self,
+                mut request: Request,
+                next: Next<'a>,
+            ) -> BoxFuture<'a, Result<Response>> {
+                if let Some(body) = &mut request.body {
+                    body["messages"][0]["content"] = "[redacted]".into();
+                }
+                next.run(request)
+            }
+        }
+
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url("http://127.0.0.1:1")
+            .with_middleware(Redact)
+            .with_middleware(Echo);
+        let response = Edgee::new(config)
+            .send("gpt-4o", "my secret")
+            .await
+            .unwrap();
+        assert_eq!(response.id, "local");
+        assert_eq!(response.text(), Some("[redacted]"));
+    }
+
+    #[tokio::test]
+    async fn test_error_responses_reach_middleware() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/v1/models/unknown")
+            .with_status(404)
+            .with_header("x-request-id", "req_1")
+            .with_body(r#"{"error": {"message": "Model not found"}}"#)
+            .create_async()
+            .await;
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let config = EdgeeConfig::new("test-key")
+            .with_base_url(server.url())
+            .with_middleware(Trace("trace", calls.clone()));
+        let err = Edgee::new(config).get_model("unknown").await.unwrap_err();
+
+        assert_eq!(err.status(), Some(404));
+        assert_eq!(
+            err.api_error().unwrap().request_id.as_deref(),
+            Some("req_1")
+        );
+        assert_eq!(*calls.lock().unwrap(), ["> trace", "< trace 404"]);
+    }
+}
@@ -1,13 +1,45 @@
+use crate::{cost::CostTracker, middleware::Middleware, retry::RetryPolicy};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Configuration for the Edgee client
-#[derive(Debug, Clone)]
+///
+/// The API key is redacted from the `Debug` output.
+#[derive(Clone)]
 pub struct EdgeeConfig {
     /// API key for authentication
     pub api_key: String,
     /// Base URL for the API (default: <https://api.edgee.ai>)
     pub base_url: String,
+    /// Retry policy for failed requests
+    pub retry: RetryPolicy,
+    /// Total timeout for a request, from connection to the end of the body
+    pub timeout: Option<Duration>,
+    /// Timeout for establishing the connection
+    pub connect_timeout: Option<Duration>,
+    /// Timeout between two reads of the response body
+    pub read_timeout: Option<Duration>,
+    /// Proxy used for all requests (system proxies are used when unset)
+    pub proxy: Option<reqwest::Proxy>,
+    /// Additional trusted root certificates
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub root_certificates: Vec<reqwest::Certificate>,
+    /// User-Agent header sent with every request (default: `edgee-rust/<version>`)
+    pub user_agent: Option<String>,
+    /// Headers sent with every request
+    pub default_headers: HashMap<String, String>,
+    /// How malformed chunks are handled in streaming responses
+    pub stream_errors: StreamErrorMode,
+    /// Tracker recording the usage and cost of every response
+    pub cost_tracker: Option<CostTracker>,
+    /// How long the model catalog is cached (not cached when unset)
+    pub model_cache_ttl: Option<Duration>,
+    /// Middleware wrapping every request, outermost first
+    pub middlewares: Vec<Arc<dyn Middleware>>,
 }
 
 impl EdgeeConfig {
@@ -16,6 +48,19 @@ impl EdgeeConfig {
         Self {
             api_key: api_key.into(),
             base_url: "https://api.edgee.ai".to_string(),
+            retry: RetryPolicy::default(),
+            timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+            proxy: None,
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            root_certificates: Vec::new(),
+            user_agent: None,
+            default_headers: HashMap::new(),
+            stream_errors: StreamErrorMode::default(),
+            cost_tracker: None,
+            model_cache_ttl: None,
+            middlewares: Vec::new(),
         }
     }
 
@@ -25,18 +70,137 @@ impl EdgeeConfig {
         self
     }
 
+    /// Set the retry policy for failed requests
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the total timeout for a request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for establishing the connection
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout between two reads of the response body
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Route all requests through the given proxy
+    pub fn with_proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust an additional root certificate
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn with_root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Set the User-Agent header
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Add a header sent with every request
+    pub fn with_default_header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.default_headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set how malformed chunks are handled in streaming responses
+    pub fn with_stream_errors(mut self, mode: StreamErrorMode) -> Self {
+        self.stream_errors = mode;
+        self
+    }
+
+    /// Record the usage and cost of every response in the given tracker
+    pub fn with_cost_tracker(mut self, tracker: CostTracker) -> Self {
+        self.cost_tracker = Some(tracker);
+        self
+    }
+
+    /// Add a middleware around every request
+    ///
+    /// Middleware run in the order they are added, around the built-in
+    /// retries. They are not run by the blocking client.
+    pub fn with_middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Cache the model catalog for the given duration
+    pub fn with_model_cache(mut self, ttl: Duration) -> Self {
+        self.model_cache_ttl = Some(ttl);
+        self
+    }
+
     /// Create configuration from environment variables
     /// Reads EDGEE_API_KEY and optionally EDGEE_BASE_URL
     pub fn from_env() -> crate::Result<Self> {
         let api_key = std::env::var("EDGEE_API_KEY").map_err(|_| crate::Error::MissingApiKey)?;
 
-        let base_url =
-            std::env::var("EDGEE_BASE_URL").unwrap_or_else(|_| "https://api.edgee.ai".to_string());
+        let mut config = Self::new(api_key);
+        if let Ok(base_url) = std::env::var("EDGEE_BASE_URL") {
+            config.base_url = base_url;
+        }
+
+        Ok(config)
+    }
+}
 
-        Ok(Self { api_key, base_url })
+impl std::fmt::Debug for EdgeeConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("EdgeeConfig");
+        debug
+            .field("api_key", &"[REDACTED]")
+            .field("base_url", &self.base_url)
+            .field("retry", &self.retry)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("proxy", &self.proxy);
+        #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+        debug.field("root_certificates", &self.root_certificates);
+        debug
+            .field("user_agent", &self.user_agent)
+            .field("default_headers", &self.default_headers)
+            .field("stream_errors", &self.stream_errors)
+            .field("cost_tracker", &self.cost_tracker)
+            .field("model_cache_ttl", &self.model_cache_ttl)
+            .field("middlewares", &self.middlewares)
+            .finish()
     }
 }
 
+/// How malformed chunks are handled in streaming responses
+///
+/// Error objects sent by the gateway in the middle of a stream are always
+/// yielded as [`Error::Stream`](crate::Error::Stream), whatever the mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StreamErrorMode {
+    /// Silently skip chunks that cannot be parsed
+    #[default]
+    Lenient,
+    /// Yield an [`Error::Stream`](crate::Error::Stream) for chunks that cannot be parsed
+    Strict,
+}
+
 /// Message role in a conversation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -64,12 +228,24 @@ pub struct ToolCall {
     pub function: FunctionCall,
 }
 
+impl ToolCall {
+    /// Deserialize the JSON arguments of the call
+    pub fn parse_arguments<T: serde::de::DeserializeOwned>(&self) -> crate::Result<T> {
+        serde_json::from_str(&self.function.arguments).map_err(|source| {
+            crate::Error::ToolArguments {
+                name: self.function.name.clone(),
+                source,
+            }
+        })
+    }
+}
+
 /// Message in a conversation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    pub content: Option<Content>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -81,7 +257,7 @@ impl Message {
     pub fn system(content: impl Into<String>) -> Self {
         Self {
             role: Role::System,
-            content: Some(content.into()),
+            content: Some(Content::Text(content.into())),
             tool_calls: None,
             tool_call_id: None,
         }
@@ -91,7 +267,7 @@ impl Message {
     pub fn developer(content: impl Into<String>) -> Self {
         Self {
             role: Role::Developer,
-            content: Some(content.into()),
+            content: Some(Content::Text(content.into())),
             tool_calls: None,
             tool_call_id: None,
         }
@@ -101,7 +277,7 @@ impl Message {
     pub fn user(content: impl Into<String>) -> Self {
         Self {
             role: Role::User,
-            content: Some(content.into()),
+            content: Some(Content::Text(content.into())),
             tool_calls: None,
             tool_call_id: None,
         }
@@ -111,7 +287,7 @@ impl Message {
     pub fn assistant(content: impl Into<String>) -> Self {
         Self {
             role: Role::Assistant,
-            content: Some(content.into()),
+            content: Some(Content::Text(content.into())),
             tool_calls: None,
             tool_call_id: None,
         }
@@ -121,11 +297,262 @@ impl Message {
     pub fn tool(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
         Self {
             role: Role::Tool,
-            content: Some(content.into()),
+            content: Some(Content::Text(content.into())),
             tool_calls: None,
             tool_call_id: Some(tool_call_id.into()),
         }
     }
+
+    /// Create a user message made of content parts
+    pub fn user_with_parts(parts: Vec<ContentPart>) -> Self {
+        Self {
+            role: Role::User,
+            content: Some(Content::Parts(parts)),
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// Create a user message with a text and an image URL (or data URL)
+    pub fn user_with_image(text: impl Into<String>, image_url: impl Into<String>) -> Self {
+        Self::user_with_parts(vec![
+            ContentPart::text(text),
+            ContentPart::image_url(image_url),
+        ])
+    }
+
+    /// Get the text content of the message
+    ///
+    /// See [`Content::as_text`].
+    pub fn text(&self) -> Option<&str> {
+        self.content.as_ref().and_then(Content::as_text)
+    }
+}
+
+/// Content of a message: plain text, or a list of typed parts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    /// Plain text
+    Text(String),
+    /// Text, images, audio and files
+    Parts(Vec<ContentPart>),
+}
+
+impl Content {
+    /// Get the text, if the content is plain text or a single text part
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Content::Text(text) => Some(text),
+            Content::Parts(parts) => match parts.as_slice() {
+                [ContentPart::Text { text }] => Some(text),
+                _ => None,
+            },
+        }
+    }
+
+    /// Concatenate all the text of the content, ignoring non-text parts
+    pub fn to_text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for Content {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Content::Parts(parts)
+    }
+}
+
+/// Typed part of a message content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// Text
+    Text { text: String },
+    /// Image, referenced by URL or inlined as a data URL
+    ImageUrl { image_url: ImageUrl },
+    /// Base64-encoded audio
+    InputAudio { input_audio: InputAudio },
+    /// File, inlined as a data URL or referenced by ID
+    File { file: FileContent },
+}
+
+impl ContentPart {
+    /// Create a text part
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+
+    /// Create an image part from a URL (or data URL)
+    pub fn image_url(url: impl Into<String>) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+
+    /// Create an image part from a URL with the given level of detail
+    pub fn image_url_with_detail(url: impl Into<String>, detail: ImageDetail) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: Some(detail),
+            },
+        }
+    }
+
+    /// Create an image part inlining the given bytes (e.g. `image/png`)
+    pub fn image_bytes(bytes: &[u8], mime_type: &str) -> Self {
+        Self::image_url(data_url(mime_type, bytes))
+    }
+
+    /// Create an image part inlining a local file
+    ///
+    /// The MIME type is guessed from the file extension.
+    pub fn image_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        Ok(Self::image_bytes(&bytes, mime_type_from_path(path)))
+    }
+
+    /// Create an audio part from raw bytes in the given format (e.g. `wav`, `mp3`)
+    pub fn input_audio(bytes: &[u8], format: impl Into<String>) -> Self {
+        ContentPart::InputAudio {
+            input_audio: InputAudio {
+                data: BASE64.encode(bytes),
+                format: format.into(),
+            },
+        }
+    }
+
+    /// Create a file part inlining the given bytes
+    pub fn file_bytes(filename: impl Into<String>, bytes: &[u8], mime_type: &str) -> Self {
+        ContentPart::File {
+            file: FileContent {
+                file_data: Some(data_url(mime_type, bytes)),
+                file_id: None,
+                filename: Some(filename.into()),
+            },
+        }
+    }
+
+    /// Create a file part inlining a local file
+    ///
+    /// The MIME type is guessed from the file extension.
+    pub fn file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::file_bytes(
+            filename,
+            &bytes,
+            mime_type_from_path(path),
+        ))
+    }
+
+    /// Create a file part referencing a previously uploaded file
+    pub fn file_id(file_id: impl Into<String>) -> Self {
+        ContentPart::File {
+            file: FileContent {
+                file_data: None,
+                file_id: Some(file_id.into()),
+                filename: None,
+            },
+        }
+    }
+}
+
+/// Image referenced by a content part
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// URL or data URL of the image
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>,
+}
+
+/// Level of detail used by the model to process an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageDetail {
+    Auto,
+    Low,
+    High,
+}
+
+/// Audio inlined in a content part
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputAudio {
+    /// Base64-encoded audio data
+    pub data: String,
+    /// Audio format, e.g. `wav` or `mp3`
+    pub format: String,
+}
+
+/// File attached to a content part
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileContent {
+    /// Data URL of the file content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_data: Option<String>,
+    /// ID of a previously uploaded file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+}
+
+/// Build a base64 data URL from raw bytes
+pub fn data_url(mime_type: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", mime_type, BASE64.encode(bytes))
+}
+
+/// Guess the MIME type of a file from its extension
+fn mime_type_from_path(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("csv") => "text/csv",
+        Some("json") => "application/json",
+        Some("wav") => "audio/wav",
+        Some("mp3") => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
 }
 
 /// JSON Schema for function parameters
@@ -169,20 +596,265 @@ impl Tool {
 }
 
 /// Tool choice configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
+///
+/// Serialized to the OpenAI wire format: `"auto"`, `"none"` and `"required"`
+/// for modes, and objects for specific functions and allowed tool subsets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ToolChoiceRepr", into = "ToolChoiceRepr")]
 pub enum ToolChoice {
-    /// Auto-select tools
+    /// Let the model decide whether to call tools
     Auto,
-    /// Never use tools
+    /// Never call tools
     None,
-    /// Use a specific tool
-    Specific {
-        r#type: String,
-        function: HashMap<String, String>,
+    /// Call at least one tool
+    Required,
+    /// Call the function with the given name
+    Function { name: String },
+    /// Only consider the functions with the given names
+    AllowedTools {
+        mode: AllowedToolsMode,
+        tools: Vec<String>,
     },
 }
 
+impl ToolChoice {
+    /// Force a call to the function with the given name
+    pub fn function(name: impl Into<String>) -> Self {
+        ToolChoice::Function { name: name.into() }
+    }
+
+    /// Restrict the model to the functions with the given names
+    pub fn allowed_tools(
+        mode: AllowedToolsMode,
+        tools: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        ToolChoice::AllowedTools {
+            mode,
+            tools: tools.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Whether the model must call one of the allowed tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllowedToolsMode {
+    /// The model may call one of the allowed tools
+    Auto,
+    /// The model must call one of the allowed tools
+    Required,
+}
+
+/// Wire representation of [`ToolChoice`]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ToolChoiceRepr {
+    Mode(ToolChoiceMode),
+    Object(ToolChoiceObject),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ToolChoiceMode {
+    Auto,
+    None,
+    Required,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ToolChoiceObject {
+    Function { function: FunctionName },
+    AllowedTools { allowed_tools: AllowedTools },
+}
+
+#[derive(Serialize, Deserialize)]
+struct AllowedTools {
+    mode: AllowedToolsMode,
+    tools: Vec<ToolReference>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ToolReference {
+    Function { function: FunctionName },
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionName {
+    name: String,
+}
+
+impl From<ToolChoice> for ToolChoiceRepr {
+    fn from(choice: ToolChoice) -> Self {
+        match choice {
+            ToolChoice::Auto => ToolChoiceRepr::Mode(ToolChoiceMode::Auto),
+            ToolChoice::None => ToolChoiceRepr::Mode(ToolChoiceMode::None),
+            ToolChoice::Required => ToolChoiceRepr::Mode(ToolChoiceMode::Required),
+            ToolChoice::Function { name } => ToolChoiceRepr::Object(ToolChoiceObject::Function {
+                function: FunctionName { name },
+            }),
+            ToolChoice::AllowedTools { mode, tools } => {
+                ToolChoiceRepr::Object(ToolChoiceObject::AllowedTools {
+                    allowed_tools: AllowedTools {
+                        mode,
+                        tools: tools
+                            .into_iter()
+                            .map(|name| ToolReference::Function {
+                                function: FunctionName { name },
+                            })
+                            .collect(),
+                    },
+                })
+            }
+        }
+    }
+}
+
+impl From<ToolChoiceRepr> for ToolChoice {
+    fn from(repr: ToolChoiceRepr) -> Self {
+        match repr {
+            ToolChoiceRepr::Mode(ToolChoiceMode::Auto) => ToolChoice::Auto,
+            ToolChoiceRepr::Mode(ToolChoiceMode::None) => ToolChoice::None,
+            ToolChoiceRepr::Mode(ToolChoiceMode::Required) => ToolChoice::Required,
+            ToolChoiceRepr::Object(ToolChoiceObject::Function { function }) => {
+                ToolChoice::Function {
+                    name: function.name,
+                }
+            }
+            ToolChoiceRepr::Object(ToolChoiceObject::AllowedTools { allowed_tools }) => {
+                ToolChoice::AllowedTools {
+                    mode: allowed_tools.mode,
+                    tools: allowed_tools
+                        .tools
+                        .into_iter()
+                        .map(|ToolReference::Function { function }| function.name)
+                        .collect(),
+                }
+            }
+        }
+    }
+}
+
+/// Format the model must reply in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Free-form text
+    Text,
+    /// Any valid JSON object
+    JsonObject,
+    /// JSON matching the given schema
+    JsonSchema { json_schema: JsonSchemaFormat },
+}
+
+impl ResponseFormat {
+    /// Require a reply matching the given JSON schema, in strict mode
+    pub fn json_schema(name: impl Into<String>, schema: serde_json::Value) -> Self {
+        ResponseFormat::JsonSchema {
+            json_schema: JsonSchemaFormat {
+                name: name.into(),
+                description: None,
+                schema,
+                strict: Some(true),
+            },
+        }
+    }
+
+    /// Require a reply matching the JSON schema of `T`, in strict mode
+    ///
+    /// The schema is adapted to the requirements of strict mode: objects
+    /// reject additional properties and list all their properties as
+    /// required (optional fields remain nullable).
+    #[cfg(feature = "schemars")]
+    pub fn json_schema_for<T: schemars::JsonSchema>() -> Self {
+        let name: String = T::schema_name()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        let mut schema = serde_json::Value::from(schemars::schema_for!(T));
+        if let Some(object) = schema.as_object_mut() {
+            object.remove("$schema");
+        }
+        make_strict(&mut schema);
+
+        Self::json_schema(name, schema)
+    }
+}
+
+/// Adapt a JSON schema to the requirements of strict structured outputs
+///
+/// Only subschemas are visited, so that the `properties` map itself is never
+/// mistaken for a schema.
+#[cfg(feature = "schemars")]
+fn make_strict(schema: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    let Value::Object(object) = schema else {
+        return;
+    };
+
+    let required = match object.get_mut("properties") {
+        Some(Value::Object(properties)) => {
+            properties.values_mut().for_each(make_strict);
+            Some(properties.keys().cloned().map(Value::String).collect())
+        }
+        _ => None,
+    };
+    for key in ["items", "additionalProperties", "not"] {
+        match object.get_mut(key) {
+            Some(Value::Array(schemas)) => schemas.iter_mut().for_each(make_strict),
+            Some(schema) => make_strict(schema),
+            None => {}
+        }
+    }
+    for key in ["anyOf", "oneOf", "allOf", "prefixItems"] {
+        if let Some(Value::Array(schemas)) = object.get_mut(key) {
+            schemas.iter_mut().for_each(make_strict);
+        }
+    }
+    for key in ["$defs", "definitions"] {
+        if let Some(Value::Object(definitions)) = object.get_mut(key) {
+            definitions.values_mut().for_each(make_strict);
+        }
+    }
+
+    if let Some(required) = required {
+        object.insert("required".to_string(), Value::Array(required));
+        object.insert("additionalProperties".to_string(), false.into());
+    }
+}
+
+/// JSON schema a structured reply must match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    /// Name of the schema (letters, digits, `_` and `-` only)
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: serde_json::Value,
+    /// Whether the model must follow the schema exactly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// Reasoning effort for reasoning models
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
 /// Input for the chat completion request
 #[derive(Debug, Clone, Serialize)]
 pub struct InputObject {
@@ -190,9 +862,47 @@ pub struct InputObject {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_choice: Option<serde_json::Value>,
+    pub tool_choice: Option<ToolChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Sampling temperature (0.0-2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Nucleus sampling probability mass (0.0-1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// Maximum number of tokens to generate (legacy, see `max_completion_tokens`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Maximum number of tokens to generate, including reasoning tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    /// Sequences where the model stops generating
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Penalty for tokens already present in the text (-2.0-2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    /// Penalty proportional to the frequency of tokens in the text (-2.0-2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// Seed for deterministic sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Number of choices to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// Bias added to the logits of the given token IDs (-100-100)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<u32, i32>>,
+    /// Identifier of the end user, for abuse monitoring
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Reasoning effort for reasoning models
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
     /// Enable token compression for this request (overrides API key settings if present)
     /// This is a gateway-internal field and is never sent to providers.
     #[serde(default, skip_serializing)]
@@ -201,6 +911,10 @@ pub struct InputObject {
     /// This is a gateway-internal field and is never sent to providers.
     #[serde(default, skip_serializing)]
     pub compression_rate: Option<f64>,
+    /// Client-side cache options for this request, never sent to the gateway
+    #[cfg(feature = "cache")]
+    #[serde(skip)]
+    pub cache_control: Option<crate::cache::CacheControl>,
 }
 
 impl InputObject {
@@ -211,8 +925,23 @@ impl InputObject {
             tools: None,
             tool_choice: None,
             tags: None,
+            response_format: None,
+            temperature: None,
+            top_p: None,
+            max_tokens: None,
+            max_completion_tokens: None,
+            stop: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            seed: None,
+            n: None,
+            logit_bias: None,
+            user: None,
+            reasoning_effort: None,
             enable_compression: None,
             compression_rate: None,
+            #[cfg(feature = "cache")]
+            cache_control: None,
         }
     }
 
@@ -223,7 +952,7 @@ impl InputObject {
     }
 
     /// Set tool choice
-    pub fn with_tool_choice(mut self, tool_choice: serde_json::Value) -> Self {
+    pub fn with_tool_choice(mut self, tool_choice: ToolChoice) -> Self {
         self.tool_choice = Some(tool_choice);
         self
     }
@@ -234,6 +963,84 @@ impl InputObject {
         self
     }
 
+    /// Set the format the model must reply in
+    pub fn with_response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+
+    /// Set the sampling temperature (0.0-2.0)
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Set the nucleus sampling probability mass (0.0-1.0)
+    pub fn with_top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Set the maximum number of tokens to generate (legacy `max_tokens` field)
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Set the maximum number of tokens to generate, including reasoning tokens
+    pub fn with_max_completion_tokens(mut self, max_completion_tokens: u32) -> Self {
+        self.max_completion_tokens = Some(max_completion_tokens);
+        self
+    }
+
+    /// Set the sequences where the model stops generating
+    pub fn with_stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Set the presence penalty (-2.0-2.0)
+    pub fn with_presence_penalty(mut self, penalty: f64) -> Self {
+        self.presence_penalty = Some(penalty);
+        self
+    }
+
+    /// Set the frequency penalty (-2.0-2.0)
+    pub fn with_frequency_penalty(mut self, penalty: f64) -> Self {
+        self.frequency_penalty = Some(penalty);
+        self
+    }
+
+    /// Set the seed for deterministic sampling
+    pub fn with_seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Set the number of choices to generate
+    pub fn with_n(mut self, n: u32) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    /// Set the bias added to the logits of token IDs (-100-100)
+    pub fn with_logit_bias(mut self, logit_bias: HashMap<u32, i32>) -> Self {
+        self.logit_bias = Some(logit_bias);
+        self
+    }
+
+    /// Set the identifier of the end user
+    pub fn with_user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Set the reasoning effort for reasoning models
+    pub fn with_reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(effort);
+        self
+    }
+
     /// Enable or disable token compression for this request
     pub fn with_compression(mut self, enable: bool) -> Self {
         self.enable_compression = Some(enable);
@@ -245,16 +1052,33 @@ impl InputObject {
         self.compression_rate = Some(rate);
         self
     }
+
+    /// Set the client-side cache options for this request
+    #[cfg(feature = "cache")]
+    pub fn with_cache_control(mut self, cache_control: crate::cache::CacheControl) -> Self {
+        self.cache_control = Some(cache_control);
+        self
+    }
 }
 
 /// Token usage information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
+impl std::ops::AddAssign<&Usage> for Usage {
+    fn add_assign(&mut self, other: &Usage) {
+        self.prompt_tokens = self.prompt_tokens.saturating_add(other.prompt_tokens);
+        self.completion_tokens = self
+            .completion_tokens
+            .saturating_add(other.completion_tokens);
+        self.total_tokens = self.total_tokens.saturating_add(other.total_tokens);
+    }
+}
+
 /// Compression information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Compression {
@@ -283,14 +1107,15 @@ pub struct SendResponse {
     pub usage: Option<Usage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub compression: Option<Compression>,
+    /// Whether the response was served from the client-side cache
+    #[serde(skip)]
+    pub cache_hit: bool,
 }
 
 impl SendResponse {
     /// Get the text content from the first choice
     pub fn text(&self) -> Option<&str> {
-        self.choices
-            .first()
-            .and_then(|c| c.message.content.as_deref())
+        self.choices.first().and_then(|c| c.message.text())
     }
 
     /// Get the message from the first choice
@@ -311,6 +1136,28 @@ impl SendResponse {
             .first()
             .and_then(|c| c.message.tool_calls.as_ref())
     }
+
+    /// Deserialize the JSON text content of the first choice
+    ///
+    /// Markdown code fences around the JSON are ignored.
+    pub fn parse<T: serde::de::DeserializeOwned>(&self) -> crate::Result<T> {
+        let text = self.text().ok_or_else(|| crate::Error::StructuredOutput {
+            message: "the response has no text content".to_string(),
+            content: None,
+        })?;
+
+        let json = text.trim();
+        let json = json
+            .strip_prefix("```json")
+            .or_else(|| json.strip_prefix("```"))
+            .and_then(|json| json.strip_suffix("```"))
+            .unwrap_or(json);
+
+        serde_json::from_str(json).map_err(|e| crate::Error::StructuredOutput {
+            message: e.to_string(),
+            content: Some(text.to_string()),
+        })
+    }
 }
 
 /// Delta in a streaming response
@@ -321,7 +1168,35 @@ pub struct StreamDelta {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tool_calls: Option<Vec<ToolCall>>,
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// Fragment of a function call in a streaming response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FunctionCallDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+/// Fragment of a tool call in a streaming response
+///
+/// The id, type and function name usually only come with the first fragment
+/// of a call; following fragments carry slices of the arguments. Fragments
+/// belonging to the same call share the same `index`. Use
+/// [`ToolCallAccumulator`](crate::stream::ToolCallAccumulator) to merge them
+/// into complete [`ToolCall`]s.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    #[serde(default)]
+    pub index: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub call_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<FunctionCallDelta>,
 }
 
 /// Choice in a streaming response
@@ -340,7 +1215,14 @@ pub struct StreamChunk {
     pub object: String,
     pub created: u64,
     pub model: String,
+    #[serde(default)]
     pub choices: Vec<StreamChoice>,
+    /// Token usage, usually sent with the last chunk only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Compression information, usually sent with the last chunk only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
 }
 
 impl StreamChunk {
@@ -356,6 +1238,13 @@ impl StreamChunk {
         self.choices.first().and_then(|c| c.delta.role.as_ref())
     }
 
+    /// Get the tool call fragments from the first choice delta
+    pub fn tool_calls(&self) -> Option<&Vec<ToolCallDelta>> {
+        self.choices
+            .first()
+            .and_then(|c| c.delta.tool_calls.as_ref())
+    }
+
     /// Get the finish reason from the first choice
     pub fn finish_reason(&self) -> Option<&str> {
         self.choices
@@ -368,6 +1257,27 @@ impl StreamChunk {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_usage_add_saturates() {
+        let mut usage = Usage {
+            prompt_tokens: u32::MAX - 1,
+            completion_tokens: 1,
+            total_tokens: u32::MAX,
+        };
+        usage += &usage.clone();
+        assert_eq!(usage.prompt_tokens, u32::MAX);
+        assert_eq!(usage.completion_tokens, 2);
+        assert_eq!(usage.total_tokens, u32::MAX);
+    }
+
+    #[test]
+    fn test_config_debug_redacts_api_key() {
+        let config = EdgeeConfig::new("sk-secret").with_base_url("http://localhost");
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("sk-secret"));
+        assert!(debug.contains("http://localhost"));
+    }
+
     #[test]
     fn test_send_response_with_compression() {
         let json = r#"{
@@ -427,4 +1337,212 @@ mod tests {
         assert_eq!(input.enable_compression, Some(true));
         assert_eq!(input.compression_rate, Some(0.5));
     }
+
+    #[test]
+    fn test_content_serialization() {
+        let message = Message::user("Hello");
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::json!({"role": "user", "content": "Hello"})
+        );
+
+        let message = Message::user_with_parts(vec![
+            ContentPart::text("What is in this image?"),
+            ContentPart::image_url_with_detail("https://example.com/cat.png", ImageDetail::Low),
+            ContentPart::input_audio(b"RIFF", "wav"),
+            ContentPart::file_bytes("notes.txt", b"hi", "text/plain"),
+        ]);
+        assert_eq!(
+            serde_json::to_value(&message).unwrap(),
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "What is in this image?"},
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.png", "detail": "low"}},
+                    {"type": "input_audio", "input_audio": {"data": "UklGRg==", "format": "wav"}},
+                    {"type": "file", "file": {"file_data": "data:text/plain;base64,aGk=", "filename": "notes.txt"}}
+                ]
+            })
+        );
+
+        let parsed: Message =
+            serde_json::from_value(serde_json::to_value(&message).unwrap()).unwrap();
+        assert_eq!(parsed.content, message.content);
+    }
+
+    #[test]
+    fn test_content_text() {
+        assert_eq!(Message::user("Hello").text(), Some("Hello"));
+
+        let message = Message::user_with_image("Describe this", "https://example.com/cat.png");
+        assert_eq!(message.text(), None);
+        assert_eq!(message.content.unwrap().to_text(), "Describe this");
+
+        let content = Content::Parts(vec![ContentPart::text("only text")]);
+        assert_eq!(content.as_text(), Some("only text"));
+
+        assert_eq!(
+            ContentPart::image_bytes(&[0xff, 0xd8], "image/jpeg"),
+            ContentPart::image_url("data:image/jpeg;base64,/9g=")
+        );
+    }
+
+    #[test]
+    fn test_response_format_serialization() {
+        assert_eq!(
+            serde_json::to_value(ResponseFormat::JsonObject).unwrap(),
+            serde_json::json!({"type": "json_object"})
+        );
+        assert_eq!(
+            serde_json::to_value(ResponseFormat::json_schema(
+                "answer",
+                serde_json::json!({"type": "object"})
+            ))
+            .unwrap(),
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {"name": "answer", "schema": {"type": "object"}, "strict": true}
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_structured_response() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Answer {
+            value: u32,
+        }
+
+        let response = |content: &str| -> SendResponse {
+            serde_json::from_value(serde_json::json!({
+                "id": "test-id",
+                "object": "chat.completion",
+                "created": 1234567890,
+                "model": "gpt-4o",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": content},
+                    "finish_reason": "stop"
+                }]
+            }))
+            .unwrap()
+        };
+
+        let answer: Answer = response(r#"{"value": 42}"#).parse().unwrap();
+        assert_eq!(answer, Answer { value: 42 });
+
+        let answer: Answer = response("```json\n{\"value\": 7}\n```").parse().unwrap();
+        assert_eq!(answer, Answer { value: 7 });
+
+        match response("The answer is 42").parse::<Answer>() {
+            Err(crate::Error::StructuredOutput { content, .. }) => {
+                assert_eq!(content.as_deref(), Some("The answer is 42"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_json_schema_for_type() {
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Person {
+            name: String,
+            nickname: Option<String>,
+        }
+
+        let ResponseFormat::JsonSchema { json_schema } =
+            ResponseFormat::json_schema_for::<Person>()
+        else {
+            panic!("expected a JSON schema format");
+        };
+        assert_eq!(json_schema.name, "Person");
+        assert_eq!(json_schema.strict, Some(true));
+        assert_eq!(json_schema.schema["additionalProperties"], false);
+        assert_eq!(
+            json_schema.schema["required"],
+            serde_json::json!(["name", "nickname"])
+        );
+        assert!(json_schema.schema.get("$schema").is_none());
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_json_schema_for_field_named_properties() {
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Property {
+            key: String,
+        }
+
+        #[derive(schemars::JsonSchema)]
+        #[allow(dead_code)]
+        struct Listing {
+            name: String,
+            properties: Vec<Property>,
+        }
+
+        let ResponseFormat::JsonSchema { json_schema } =
+            ResponseFormat::json_schema_for::<Listing>()
+        else {
+            panic!("expected a JSON schema format");
+        };
+        let schema = json_schema.schema;
+        let fields: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+        assert_eq!(fields, ["name", "properties"]);
+        assert!(schema["properties"]["properties"].get("required").is_none());
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["name", "properties"])
+        );
+
+        let property = &schema["$defs"]["Property"];
+        assert_eq!(property["required"], serde_json::json!(["key"]));
+        assert_eq!(property["additionalProperties"], false);
+    }
+
+    #[test]
+    fn test_tool_choice_wire_format() {
+        let cases = [
+            (ToolChoice::Auto, serde_json::json!("auto")),
+            (ToolChoice::None, serde_json::json!("none")),
+            (ToolChoice::Required, serde_json::json!("required")),
+            (
+                ToolChoice::function("get_weather"),
+                serde_json::json!({"type": "function", "function": {"name": "get_weather"}}),
+            ),
+            (
+                ToolChoice::allowed_tools(AllowedToolsMode::Required, ["get_weather", "get_time"]),
+                serde_json::json!({
+                    "type": "allowed_tools",
+                    "allowed_tools": {
+                        "mode": "required",
+                        "tools": [
+                            {"type": "function", "function": {"name": "get_weather"}},
+                            {"type": "function", "function": {"name": "get_time"}}
+                        ]
+                    }
+                }),
+            ),
+        ];
+
+        for (choice, wire) in cases {
+            assert_eq!(serde_json::to_value(&choice).unwrap(), wire);
+            assert_eq!(serde_json::from_value::<ToolChoice>(wire).unwrap(), choice);
+        }
+
+        assert!(serde_json::from_value::<ToolChoice>(serde_json::json!("sometimes")).is_err());
+    }
+
+    #[test]
+    fn test_input_object_tool_choice() {
+        let input = InputObject::new(vec![Message::user("Hello")])
+            .with_tool_choice(ToolChoice::function("get_weather"));
+
+        assert_eq!(
+            serde_json::to_value(&input).unwrap()["tool_choice"],
+            serde_json::json!({"type": "function", "function": {"name": "get_weather"}})
+        );
+    }
 }
@@ -0,0 +1,349 @@
+use crate::telemetry;
+use reqwest::header::HeaderMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Retry policy applied to gateway requests
+///
+/// Failed attempts are retried with exponential backoff. When the gateway
+/// answers with a `Retry-After`, `retry-after-ms` or `x-ratelimit-reset`
+/// header, the server-provided delay is used instead of the computed one.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one (1 disables retries)
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every following retry
+    pub base_delay: Duration,
+    /// Upper bound for the computed backoff delay
+    pub max_delay: Duration,
+    /// Fraction of the backoff delay that is randomized (0.0-1.0)
+    pub jitter: f64,
+    /// HTTP statuses that trigger a retry
+    pub retryable_statuses: Vec<u16>,
+    /// Retry when the request timed out
+    pub retry_on_timeout: bool,
+    /// Retry when the connection could not be established
+    pub retry_on_connect: bool,
+    /// Server-provided delays above this value are ignored in favor of backoff
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+            jitter: 0.25,
+            retryable_statuses: vec![408, 409, 429, 500, 502, 503, 504],
+            retry_on_timeout: true,
+            retry_on_connect: true,
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create the default retry policy (3 attempts, 500ms base delay)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Set the maximum number of attempts, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Set the upper bound for the computed backoff delay
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the randomized fraction of the backoff delay (0.0-1.0)
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the HTTP statuses that trigger a retry
+    pub fn with_retryable_statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.retryable_statuses = statuses;
+        self
+    }
+
+    /// Enable or disable retries on request timeouts
+    pub fn with_retry_on_timeout(mut self, retry: bool) -> Self {
+        self.retry_on_timeout = retry;
+        self
+    }
+
+    /// Enable or disable retries on connection failures
+    pub fn with_retry_on_connect(mut self, retry: bool) -> Self {
+        self.retry_on_connect = retry;
+        self
+    }
+
+    /// Set the largest server-provided delay that is honoured
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Whether a response with the given status should be retried
+    pub fn is_retryable_status(&self, status: u16) -> bool {
+        self.retryable_statuses.contains(&status)
+    }
+
+    /// Whether a transport error should be retried
+    pub fn is_retryable_error(&self, error: &reqwest::Error) -> bool {
+        (self.retry_on_timeout && error.is_timeout())
+            || (self.retry_on_connect && error.is_connect())
+    }
+
+    /// Delay to wait before the given retry (1 for the first retry)
+    ///
+    /// Uses the response headers when they carry a usable delay, and falls
+    /// back to exponential backoff otherwise.
+    pub fn delay_for(&self, retry: u32, headers: Option<&HeaderMap>) -> Duration {
+        headers
+            .and_then(server_delay)
+            .filter(|delay| *delay <= self.max_retry_after)
+            .unwrap_or_else(|| self.backoff(retry))
+    }
+
+    /// Delay to wait before retrying a failed attempt (1 for the first
+    /// attempt), or `None` if the failure is final
+    ///
+    /// Reports the retry in the current request span.
+    pub(crate) fn retry_delay(&self, attempt: u32, failure: Failure<'_>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let delay = match failure {
+            Failure::Status(status, headers) => {
+                if !self.is_retryable_status(status) {
+                    return None;
+                }
+                let delay = self.delay_for(attempt, Some(headers));
+                telemetry::retrying(attempt, delay, &format_args!("status {}", status));
+                delay
+            }
+            Failure::Transport(error) | Failure::Body(error) => {
+                let dropped = matches!(failure, Failure::Body(_)) && error.is_body();
+                if !(self.is_retryable_error(error) || (self.retry_on_connect && dropped)) {
+                    return None;
+                }
+                let delay = self.delay_for(attempt, None);
+                telemetry::retrying(attempt, delay, error);
+                delay
+            }
+        };
+        Some(delay)
+    }
+
+    /// Exponential backoff delay for the given retry, with jitter applied
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1u32 << exponent)
+            .min(self.max_delay);
+        delay.mul_f64(1.0 - self.jitter * random_unit())
+    }
+}
+
+/// Failed attempt of a request
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Failure<'a> {
+    /// The gateway answered with an unsuccessful status
+    Status(u16, &'a HeaderMap),
+    /// The request could not be sent or its response not received
+    Transport(&'a reqwest::Error),
+    /// The connection dropped before the first chunk of a successful response
+    Body(&'a reqwest::Error),
+}
+
+/// Extract a retry delay from rate-limit headers
+fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return duration_from_secs(ms / 1000.0);
+    }
+    if let Some(value) = header("retry-after") {
+        let value = value.trim();
+        if let Ok(secs) = value.parse::<f64>() {
+            return duration_from_secs(secs);
+        }
+        if let Ok(date) = httpdate::parse_http_date(value) {
+            return Some(date.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+    header("x-ratelimit-reset").and_then(parse_reset)
+}
+
+/// Parse an `x-ratelimit-reset` value
+///
+/// Gateways and providers use either a number of seconds, a unix timestamp
+/// or a Go-style duration such as `1s`, `250ms` or `6m0s`.
+fn parse_reset(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        // Values this large can only be absolute unix timestamps
+        if secs > 1_000_000_000.0 {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+            return Some(duration_from_secs(secs)?.saturating_sub(now));
+        }
+        return duration_from_secs(secs);
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&i| i > 0)?;
+        let number: f64 = rest[..split].parse().ok()?;
+        let unit_len = rest[split..]
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len() - split);
+        let secs = match &rest[split..split + unit_len] {
+            "h" => number * 3600.0,
+            "m" => number * 60.0,
+            "s" => number,
+            "ms" => number / 1000.0,
+            _ => return None,
+        };
+        total = total.checked_add(duration_from_secs(secs)?)?;
+        rest = &rest[split + unit_len..];
+    }
+    Some(total)
+}
+
+/// Convert a number of seconds from a header into a `Duration`
+///
+/// Negative values are clamped to zero; values that are not finite or do not
+/// fit in a `Duration` are rejected.
+fn duration_from_secs(secs: f64) -> Option<Duration> {
+    if !secs.is_finite() {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs.max(0.0)).ok()
+}
+
+/// Pseudo-random number in `[0, 1)`, good enough for jitter
+fn random_unit() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    hasher.write_u32(nanos);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy::new()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(350))
+            .with_jitter(0.0);
+
+        assert_eq!(policy.delay_for(1, None), Duration::from_millis(100));
+        assert_eq!(policy.delay_for(2, None), Duration::from_millis(200));
+        assert_eq!(policy.delay_for(3, None), Duration::from_millis(350));
+        assert_eq!(policy.delay_for(40, None), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let policy = RetryPolicy::new()
+            .with_base_delay(Duration::from_millis(100))
+            .with_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.delay_for(1, None);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_server_delay_headers() {
+        let policy = RetryPolicy::new().with_jitter(0.0);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("2"));
+        assert_eq!(policy.delay_for(1, Some(&headers)), Duration::from_secs(2));
+
+        headers.insert("retry-after-ms", HeaderValue::from_static("150"));
+        assert_eq!(
+            policy.delay_for(1, Some(&headers)),
+            Duration::from_millis(150)
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("0m45s"));
+        assert_eq!(policy.delay_for(1, Some(&headers)), Duration::from_secs(45));
+
+        // Delays longer than the configured maximum fall back to backoff
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("3600"));
+        assert_eq!(policy.delay_for(1, Some(&headers)), policy.base_delay);
+    }
+
+    #[test]
+    fn test_parse_reset_formats() {
+        assert_eq!(parse_reset("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_reset("soon"), None);
+    }
+
+    #[test]
+    fn test_out_of_range_headers_fall_back_to_backoff() {
+        let policy = RetryPolicy::new().with_jitter(0.0);
+
+        for (name, value) in [
+            ("retry-after", "inf"),
+            ("retry-after", "NaN"),
+            ("retry-after-ms", "1e30"),
+            ("x-ratelimit-reset", "1e300"),
+            ("x-ratelimit-reset", "99999999999999999999h"),
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_static(value));
+            assert_eq!(
+                policy.delay_for(1, Some(&headers)),
+                policy.base_delay,
+                "{name}: {value}"
+            );
+        }
+
+        assert_eq!(parse_reset("inf"), None);
+        assert_eq!(parse_reset("99999999999999999999h"), None);
+        assert_eq!(parse_reset("9999999999999999h9999999999999999h"), None);
+    }
+}
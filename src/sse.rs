@@ -0,0 +1,332 @@
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use std::{collections::VecDeque, time::Duration};
+
+/// Event type used when an event has no `event:` field
+pub const DEFAULT_EVENT: &str = "message";
+
+/// Server-sent event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Event type (`message` unless an `event:` field was sent)
+    pub event: String,
+    /// Event payload, with the lines of multi-line `data:` fields joined by `\n`
+    pub data: String,
+    /// Last event ID seen on the stream, if any
+    pub id: Option<String>,
+    /// Reconnection time sent alongside this event, if any
+    pub retry: Option<Duration>,
+}
+
+impl SseEvent {
+    /// Whether this event has the default `message` type
+    pub fn is_message(&self) -> bool {
+        self.event == DEFAULT_EVENT
+    }
+}
+
+/// Incremental decoder for `text/event-stream` bodies
+///
+/// Implements the parsing rules of the HTML specification: `\r\n`, `\n` and
+/// `\r` line endings, comments, multi-line `data:` fields, `event:`, `id:` and
+/// `retry:` fields. Bytes can be pushed in arbitrary pieces, including pieces
+/// that split a line ending or a multi-byte UTF-8 character.
+///
+/// Unlike browsers, [`SseDecoder::finish`] dispatches a trailing event that
+/// was not followed by a blank line, as some servers close the connection
+/// right after the last `data:` line.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    /// Bytes of the current, incomplete line
+    line: Vec<u8>,
+    /// Whether the previous chunk ended with `\r`, so a leading `\n` must be skipped
+    skip_lf: bool,
+    /// Whether the start of the stream has been checked for a byte order mark
+    bom_checked: bool,
+    event: Option<String>,
+    /// Data of the pending event, each `data:` line followed by `\n`
+    data: String,
+    retry: Option<Duration>,
+    last_event_id: Option<String>,
+    reconnection_time: Option<Duration>,
+}
+
+impl SseDecoder {
+    /// Create a new decoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed bytes to the decoder and return the events completed by them
+    pub fn push(&mut self, mut bytes: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+
+        if self.skip_lf {
+            self.skip_lf = false;
+            bytes = bytes.strip_prefix(b"\n").unwrap_or(bytes);
+        }
+
+        while let Some(pos) = bytes.iter().position(|&b| b == b'\n' || b == b'\r') {
+            self.line.extend_from_slice(&bytes[..pos]);
+            let line = self.take_line();
+            if line.is_empty() {
+                events.extend(self.dispatch());
+            } else {
+                self.process_line(&line);
+            }
+
+            let crlf = bytes[pos] == b'\r';
+            bytes = &bytes[pos + 1..];
+            if crlf {
+                match bytes.first() {
+                    Some(b'\n') => bytes = &bytes[1..],
+                    Some(_) => {}
+                    // The `\n` of a `\r\n` pair may come with the next chunk
+                    None => self.skip_lf = true,
+                }
+            }
+        }
+
+        self.line.extend_from_slice(bytes);
+        events
+    }
+
+    /// Signal the end of the stream and return the pending event, if any
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let line = self.take_line();
+        if !line.is_empty() {
+            self.process_line(&line);
+        }
+        self.dispatch()
+    }
+
+    /// Last event ID seen on the stream
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Last reconnection time sent by the server
+    pub fn reconnection_time(&self) -> Option<Duration> {
+        self.reconnection_time
+    }
+
+    /// Take the current line, stripping the byte order mark from the first one
+    fn take_line(&mut self) -> Vec<u8> {
+        let mut line = std::mem::take(&mut self.line);
+        if !self.bom_checked {
+            self.bom_checked = true;
+            if line.starts_with(b"\xEF\xBB\xBF") {
+                line.drain(..3);
+            }
+        }
+        line
+    }
+
+    /// Process a single, non-empty line
+    fn process_line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        if line.starts_with(':') {
+            return;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_ref(), ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse() {
+                    let retry = Duration::from_millis(ms);
+                    self.retry = Some(retry);
+                    self.reconnection_time = Some(retry);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Build the pending event and reset the event buffers
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let retry = self.retry.take();
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+
+        Some(SseEvent {
+            event: event
+                .filter(|e| !e.is_empty())
+                .unwrap_or_else(|| DEFAULT_EVENT.to_string()),
+            data,
+            id: self.last_event_id.clone(),
+            retry,
+        })
+    }
+}
+
+/// Decode a stream of body bytes into server-sent events
+///
+/// Errors of the underlying stream are forwarded as-is. The pending event
+/// is dispatched when the underlying stream ends.
+pub fn decode<S, E>(stream: S) -> impl Stream<Item = Result<SseEvent, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    struct State<S> {
+        stream: S,
+        decoder: SseDecoder,
+        pending: VecDeque<SseEvent>,
+        done: bool,
+    }
+
+    let state = State {
+        stream: Box::pin(stream),
+        decoder: SseDecoder::new(),
+        pending: VecDeque::new(),
+        done: false,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+            if state.done {
+                return None;
+            }
+
+            match state.stream.next().await {
+                Some(Ok(bytes)) => state.pending.extend(state.decoder.push(&bytes)),
+                Some(Err(e)) => return Some((Err(e), state)),
+                None => {
+                    state.done = true;
+                    state.pending.extend(state.decoder.finish());
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(pieces: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for piece in pieces {
+            events.extend(decoder.push(piece));
+        }
+        events.extend(decoder.finish());
+        events
+    }
+
+    fn data(events: &[SseEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.data.as_str()).collect()
+    }
+
+    #[test]
+    fn test_simple_events() {
+        let events = decode_all(&[b"data: one\n\ndata: two\n\n"]);
+        assert_eq!(data(&events), ["one", "two"]);
+        assert!(events.iter().all(SseEvent::is_message));
+    }
+
+    #[test]
+    fn test_line_endings() {
+        assert_eq!(
+            data(&decode_all(&[b"data: a\r\n\r\ndata: b\r\rdata: c\n\n"])),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn test_crlf_split_across_chunks() {
+        let events = decode_all(&[b"data: a\r", b"\n", b"\r", b"\ndata: b\r\n\r\n"]);
+        assert_eq!(data(&events), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_utf8_split_across_chunks() {
+        let text = "data: héllo 👋\n\n".as_bytes();
+        let pieces: Vec<&[u8]> = text.chunks(1).collect();
+        assert_eq!(data(&decode_all(&pieces)), ["héllo 👋"]);
+    }
+
+    #[test]
+    fn test_multi_line_data() {
+        let events = decode_all(&[b"data: first\ndata:second\ndata\ndata: \n\n"]);
+        assert_eq!(data(&events), ["first\nsecond\n\n"]);
+
+        let events = decode_all(&[b"data\ndata: x\n\n"]);
+        assert_eq!(data(&events), ["\nx"]);
+    }
+
+    #[test]
+    fn test_fields() {
+        let events = decode_all(&[
+            b": keep-alive\n\nevent: error\nid: 42\nretry: 1500\ndata: {}\n\ndata: next\n\n",
+        ]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "error");
+        assert_eq!(events[0].id.as_deref(), Some("42"));
+        assert_eq!(events[0].retry, Some(Duration::from_millis(1500)));
+        // The event type resets, the last event ID persists
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].id.as_deref(), Some("42"));
+        assert_eq!(events[1].retry, None);
+    }
+
+    #[test]
+    fn test_invalid_retry_and_unknown_fields_are_ignored() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"retry: soon\nfoo: bar\ndata: x\n\n");
+        assert_eq!(events[0].retry, None);
+        assert_eq!(decoder.reconnection_time(), None);
+    }
+
+    #[test]
+    fn test_events_without_data_are_not_dispatched() {
+        assert!(decode_all(&[b"event: ping\n\nid: 1\n\n"]).is_empty());
+    }
+
+    #[test]
+    fn test_trailing_event_without_blank_line() {
+        assert_eq!(
+            data(&decode_all(&[b"data: one\n\ndata: two"])),
+            ["one", "two"]
+        );
+        assert_eq!(data(&decode_all(&[b"data: one\n"])), ["one"]);
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        assert_eq!(data(&decode_all(&[b"\xEF\xBB\xBFdata: a\n\n"])), ["a"]);
+        assert_eq!(
+            data(&decode_all(&[b"\xEF", b"\xBB", b"\xBFdata: a\n\n"])),
+            ["a"]
+        );
+        assert_eq!(data(&decode_all(&[b"da", b"ta: a\n\n"])), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_decode_stream() {
+        let pieces: Vec<Result<Bytes, ()>> = vec![
+            Ok(Bytes::from_static(b"data: {\"a\":")),
+            Ok(Bytes::from_static(b"1}\n\ndata: [DONE]")),
+        ];
+        let events: Vec<_> = decode(futures::stream::iter(pieces)).collect().await;
+        let events: Vec<_> = events.into_iter().map(Result::unwrap).collect();
+        assert_eq!(data(&events), ["{\"a\":1}", "[DONE]"]);
+    }
+}
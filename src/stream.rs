@@ -0,0 +1,387 @@
+use crate::{
+    error::Result,
+    models::{
+        Choice, Compression, Content, FunctionCall, Message, Role, SendResponse, StreamChunk,
+        ToolCall, ToolCallDelta, Usage,
+    },
+};
+use futures::stream::{Stream, StreamExt};
+use std::{collections::BTreeMap, future::Future};
+
+/// Merges streamed tool call fragments into complete tool calls
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::{stream::ToolCallAccumulator, Edgee};
+/// use tokio_stream::StreamExt;
+///
+/// let client = Edgee::from_env()?;
+/// let mut stream = client.stream("gpt-4o", "What's the weather in Paris?").await?;
+///
+/// let mut tool_calls = ToolCallAccumulator::new();
+/// while let Some(chunk) = stream.next().await {
+///     if let Some(deltas) = chunk?.tool_calls() {
+///         tool_calls.extend(deltas);
+///     }
+/// }
+///
+/// for call in tool_calls.finish() {
+///     println!("{}({})", call.function.name, call.function.arguments);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallAccumulator {
+    calls: BTreeMap<u32, ToolCall>,
+}
+
+impl ToolCallAccumulator {
+    /// Create an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merge a single fragment
+    pub fn push(&mut self, delta: &ToolCallDelta) {
+        let call = self.calls.entry(delta.index).or_insert_with(|| ToolCall {
+            id: String::new(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: String::new(),
+                arguments: String::new(),
+            },
+        });
+
+        // Identifiers are sent once, but some providers repeat them on
+        // every fragment: only the first value is kept.
+        if let Some(id) = delta.id.as_deref().filter(|id| !id.is_empty()) {
+            if call.id.is_empty() {
+                call.id = id.to_string();
+            }
+        }
+        if let Some(call_type) = &delta.call_type {
+            call.call_type = call_type.clone();
+        }
+        if let Some(function) = &delta.function {
+            if let Some(name) = function.name.as_deref().filter(|name| !name.is_empty()) {
+                if call.function.name.is_empty() {
+                    call.function.name = name.to_string();
+                }
+            }
+            if let Some(arguments) = &function.arguments {
+                call.function.arguments.push_str(arguments);
+            }
+        }
+    }
+
+    /// Merge a list of fragments
+    pub fn extend<'a>(&mut self, deltas: impl IntoIterator<Item = &'a ToolCallDelta>) {
+        for delta in deltas {
+            self.push(delta);
+        }
+    }
+
+    /// Whether no fragment has been merged yet
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// Get the tool calls merged so far, ordered by index
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        self.calls.values().cloned().collect()
+    }
+
+    /// Consume the accumulator and return the complete tool calls, ordered by index
+    pub fn finish(self) -> Vec<ToolCall> {
+        self.calls.into_values().collect()
+    }
+}
+
+/// Rebuilds a complete [`SendResponse`] from streamed chunks
+///
+/// Chunks are folded per choice index: the role is taken from the first
+/// delta that carries one, text content is concatenated and tool call
+/// fragments are merged with a [`ToolCallAccumulator`].
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::{stream::StreamAccumulator, Edgee};
+/// use tokio_stream::StreamExt;
+///
+/// let client = Edgee::from_env()?;
+/// let mut stream = client.stream("gpt-4o", "Tell me a story").await?;
+///
+/// let mut accumulator = StreamAccumulator::new();
+/// while let Some(chunk) = stream.next().await {
+///     let chunk = chunk?;
+///     if let Some(text) = chunk.text() {
+///         print!("{}", text);
+///     }
+///     accumulator.push(&chunk);
+/// }
+///
+/// let response = accumulator.finish();
+/// println!("\n{:?}", response.usage);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamAccumulator {
+    id: String,
+    created: u64,
+    model: String,
+    choices: BTreeMap<u32, ChoiceState>,
+    usage: Option<Usage>,
+    compression: Option<Compression>,
+}
+
+/// Accumulated state of a single choice
+#[derive(Debug, Clone, Default)]
+struct ChoiceState {
+    role: Option<Role>,
+    content: Option<String>,
+    tool_calls: ToolCallAccumulator,
+    finish_reason: Option<String>,
+}
+
+impl StreamAccumulator {
+    /// Create an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold a chunk into the response
+    pub fn push(&mut self, chunk: &StreamChunk) {
+        if self.id.is_empty() {
+            self.id = chunk.id.clone();
+        }
+        if self.model.is_empty() {
+            self.model = chunk.model.clone();
+        }
+        if self.created == 0 {
+            self.created = chunk.created;
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage.clone();
+        }
+        if chunk.compression.is_some() {
+            self.compression = chunk.compression.clone();
+        }
+
+        for choice in &chunk.choices {
+            let state = self.choices.entry(choice.index).or_default();
+            if state.role.is_none() {
+                state.role = choice.delta.role.clone();
+            }
+            if let Some(content) = &choice.delta.content {
+                state
+                    .content
+                    .get_or_insert_with(String::new)
+                    .push_str(content);
+            }
+            if let Some(tool_calls) = &choice.delta.tool_calls {
+                state.tool_calls.extend(tool_calls);
+            }
+            if choice.finish_reason.is_some() {
+                state.finish_reason = choice.finish_reason.clone();
+            }
+        }
+    }
+
+    /// Get the response assembled from the chunks received so far
+    pub fn response(&self) -> SendResponse {
+        self.clone().finish()
+    }
+
+    /// Consume the accumulator and return the assembled response
+    pub fn finish(self) -> SendResponse {
+        let choices = self
+            .choices
+            .into_iter()
+            .map(|(index, state)| {
+                let tool_calls = state.tool_calls.finish();
+                Choice {
+                    index,
+                    message: Message {
+                        role: state.role.unwrap_or(Role::Assistant),
+                        content: state.content.map(Content::Text),
+                        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                        tool_call_id: None,
+                    },
+                    finish_reason: state.finish_reason,
+                }
+            })
+            .collect();
+
+        SendResponse {
+            id: self.id,
+            object: "chat.completion".to_string(),
+            created: self.created,
+            model: self.model,
+            choices,
+            usage: self.usage,
+            compression: self.compression,
+            cache_hit: false,
+        }
+    }
+}
+
+/// Extension methods for streams returned by [`Edgee::stream`](crate::Edgee::stream)
+pub trait ResponseStreamExt: Stream<Item = Result<StreamChunk>> {
+    /// Consume the stream and assemble the complete response
+    ///
+    /// Stops at the first error and returns it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use edgee::{Edgee, ResponseStreamExt};
+    ///
+    /// let client = Edgee::from_env()?;
+    /// let response = client
+    ///     .stream("gpt-4o", "Tell me a story")
+    ///     .await?
+    ///     .collect_response()
+    ///     .await?;
+    /// println!("{}", response.text().unwrap_or(""));
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn collect_response(self) -> impl Future<Output = Result<SendResponse>> + Send
+    where
+        Self: Sized + Send,
+    {
+        async move {
+            let mut stream = std::pin::pin!(self);
+            let mut accumulator = StreamAccumulator::new();
+            while let Some(chunk) = stream.next().await {
+                accumulator.push(&chunk?);
+            }
+            Ok(accumulator.finish())
+        }
+    }
+}
+
+impl<S> ResponseStreamExt for S where S: Stream<Item = Result<StreamChunk>> + ?Sized {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(delta: &str) -> StreamChunk {
+        serde_json::from_str(&format!(
+            r#"{{"id":"1","object":"chat.completion.chunk","created":1,"model":"gpt-4o",
+                "choices":[{{"index":0,"delta":{}}}]}}"#,
+            delta
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge_openai_fragments() {
+        let chunks = [
+            chunk(
+                r#"{"role":"assistant","tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"get_weather","arguments":""}}]}"#,
+            ),
+            chunk(r#"{"tool_calls":[{"index":0,"function":{"arguments":"{\"loc"}}]}"#),
+            chunk(
+                r#"{"tool_calls":[{"index":1,"id":"call_2","type":"function","function":{"name":"get_time","arguments":"{}"}}]}"#,
+            ),
+            chunk(r#"{"tool_calls":[{"index":0,"function":{"arguments":"ation\":\"Paris\"}"}}]}"#),
+        ];
+
+        let mut accumulator = ToolCallAccumulator::new();
+        for chunk in &chunks {
+            accumulator.extend(chunk.tool_calls().unwrap());
+        }
+
+        let calls = accumulator.finish();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].call_type, "function");
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"location":"Paris"}"#);
+        assert_eq!(calls[1].id, "call_2");
+        assert_eq!(calls[1].function.name, "get_time");
+        assert_eq!(calls[1].function.arguments, "{}");
+    }
+
+    #[test]
+    fn test_repeated_identifiers_are_not_duplicated() {
+        let mut accumulator = ToolCallAccumulator::new();
+        for arguments in ["{\"a\":", "1}"] {
+            accumulator.push(&ToolCallDelta {
+                index: 0,
+                id: Some("call_1".to_string()),
+                call_type: Some("function".to_string()),
+                function: Some(crate::models::FunctionCallDelta {
+                    name: Some("f".to_string()),
+                    arguments: Some(arguments.to_string()),
+                }),
+            });
+        }
+
+        let calls = accumulator.finish();
+        assert_eq!(calls[0].id, "call_1");
+        assert_eq!(calls[0].function.name, "f");
+        assert_eq!(calls[0].function.arguments, "{\"a\":1}");
+    }
+
+    #[tokio::test]
+    async fn test_collect_response() {
+        let chunks = vec![
+            chunk(r#"{"role":"assistant","content":"Hello"}"#),
+            chunk(r#"{"content":", world"}"#),
+            serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1,"model":"gpt-4o",
+                    "choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+            )
+            .unwrap(),
+            serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1,"model":"gpt-4o",
+                    "choices":[],
+                    "usage":{"prompt_tokens":5,"completion_tokens":3,"total_tokens":8}}"#,
+            )
+            .unwrap(),
+        ];
+
+        let response = futures::stream::iter(chunks.into_iter().map(Ok))
+            .collect_response()
+            .await
+            .unwrap();
+
+        assert_eq!(response.id, "1");
+        assert_eq!(response.object, "chat.completion");
+        assert_eq!(response.text(), Some("Hello, world"));
+        assert_eq!(response.finish_reason(), Some("stop"));
+        assert_eq!(response.message().unwrap().role, Role::Assistant);
+        assert!(response.tool_calls().is_none());
+        assert_eq!(response.usage.unwrap().total_tokens, 8);
+    }
+
+    #[test]
+    fn test_accumulate_multiple_choices_and_tool_calls() {
+        let mut accumulator = StreamAccumulator::new();
+        accumulator.push(
+            &serde_json::from_str(
+                r#"{"id":"1","object":"chat.completion.chunk","created":1,"model":"gpt-4o",
+                    "choices":[
+                        {"index":1,"delta":{"role":"assistant","content":"B"}},
+                        {"index":0,"delta":{"role":"assistant","tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"f","arguments":"{}"}}]}}
+                    ]}"#,
+            )
+            .unwrap(),
+        );
+        accumulator.push(&chunk(r#"{}"#));
+
+        let response = accumulator.finish();
+        assert_eq!(response.choices.len(), 2);
+        assert_eq!(response.choices[0].index, 0);
+        assert!(response.choices[0].message.content.is_none());
+        assert_eq!(response.tool_calls().unwrap()[0].function.name, "f");
+        assert_eq!(response.choices[1].message.text(), Some("B"));
+    }
+}
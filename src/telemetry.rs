@@ -0,0 +1,458 @@
+//! Request instrumentation
+//!
+//! With the `tracing` feature, every gateway call runs in an `edgee.request`
+//! span whose fields follow the OpenTelemetry GenAI semantic conventions:
+//!
+//! | Field | Description |
+//! |-------|-------------|
+//! | `gen_ai.operation.name` | `chat` or `embeddings` |
+//! | `gen_ai.provider.name` | Always `edgee` |
+//! | `gen_ai.request.model` | Model of the request |
+//! | `gen_ai.response.id`, `gen_ai.response.model` | Identifier and model of the response |
+//! | `gen_ai.response.finish_reasons` | Finish reasons of the choices, comma-separated |
+//! | `gen_ai.usage.input_tokens`, `gen_ai.usage.output_tokens` | Token usage |
+//! | `http.response.status_code` | Status of the last HTTP response |
+//! | `error.type` | Kind of error when the request failed |
+//! | `edgee.stream` | Whether the response is streamed |
+//! | `edgee.tags` | Tags of the request, comma-separated |
+//! | `edgee.attempts` | Number of attempts when the request was retried |
+//! | `edgee.cache_hit` | Set when the response was served from the client-side cache |
+//! | `edgee.compression.saved_tokens`, `edgee.compression.rate` | Prompt compression savings |
+//! | `edgee.latency_ms` | Duration of the request, until the end of the stream |
+//! | `edgee.time_to_first_token_ms` | Delay before the first chunk of a stream |
+//!
+//! The `otel.name`, `otel.kind` and `otel.status_code` fields are set for
+//! `tracing-opentelemetry`. Retries and skipped stream chunks are reported
+//! as events with the `edgee` target.
+//!
+//! Without the feature, this module compiles to nothing.
+
+#[cfg(feature = "tracing")]
+pub(crate) use enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::*;
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use crate::{
+        error::Error,
+        models::{Compression, SendResponse, Usage},
+    };
+    use std::{fmt::Display, future::Future, time::Duration, time::Instant};
+    use tracing::{field::Empty, Instrument, Span};
+
+    /// Span of a single gateway call
+    pub(crate) struct RequestSpan {
+        span: Span,
+        start: Instant,
+        #[cfg(feature = "stream")]
+        first_chunk: bool,
+    }
+
+    impl RequestSpan {
+        /// Open the span of a request
+        pub(crate) fn new(operation: &str, model: &str, tags: &[String], stream: bool) -> Self {
+            let span = tracing::info_span!(
+                target: "edgee",
+                "edgee.request",
+                otel.name = %format_args!("{} {}", operation, model),
+                otel.kind = "client",
+                otel.status_code = Empty,
+                gen_ai.operation.name = operation,
+                gen_ai.provider.name = "edgee",
+                gen_ai.request.model = model,
+                gen_ai.response.id = Empty,
+                gen_ai.response.model = Empty,
+                gen_ai.response.finish_reasons = Empty,
+                gen_ai.usage.input_tokens = Empty,
+                gen_ai.usage.output_tokens = Empty,
+                http.response.status_code = Empty,
+                error.type = Empty,
+                edgee.stream = stream,
+                edgee.tags = Empty,
+                edgee.attempts = Empty,
+                edgee.cache_hit = Empty,
+                edgee.compression.saved_tokens = Empty,
+                edgee.compression.rate = Empty,
+                edgee.latency_ms = Empty,
+                edgee.time_to_first_token_ms = Empty,
+            );
+            if !tags.is_empty() {
+                span.record("edgee.tags", tags.join(","));
+            }
+
+            Self {
+                span,
+                start: Instant::now(),
+                #[cfg(feature = "stream")]
+                first_chunk: true,
+            }
+        }
+
+        /// Run a future inside the span
+        pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+            future.instrument(self.span.clone())
+        }
+
+        /// Run a function inside the span
+        #[cfg(feature = "blocking")]
+        pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+            self.span.in_scope(f)
+        }
+
+        /// Record a complete response
+        pub(crate) fn record_response(&self, response: &SendResponse) {
+            self.span.record("gen_ai.response.id", response.id.as_str());
+            self.span
+                .record("gen_ai.response.model", response.model.as_str());
+            let finish_reasons: Vec<&str> = response
+                .choices
+                .iter()
+                .filter_map(|c| c.finish_reason.as_deref())
+                .collect();
+            if !finish_reasons.is_empty() {
+                self.span
+                    .record("gen_ai.response.finish_reasons", finish_reasons.join(","));
+            }
+            if response.cache_hit {
+                self.span.record("edgee.cache_hit", true);
+            }
+            if let Some(usage) = &response.usage {
+                self.record_usage(usage, response.compression.as_ref());
+            }
+        }
+
+        /// Record a chunk of a streamed response
+        #[cfg(feature = "stream")]
+        pub(crate) fn record_chunk(&mut self, chunk: &crate::models::StreamChunk) {
+            if std::mem::take(&mut self.first_chunk) {
+                self.span
+                    .record("edgee.time_to_first_token_ms", millis(self.start.elapsed()));
+                self.span.record("gen_ai.response.id", chunk.id.as_str());
+                self.span
+                    .record("gen_ai.response.model", chunk.model.as_str());
+            }
+            if let Some(reason) = chunk
+                .choices
+                .iter()
+                .find_map(|c| c.finish_reason.as_deref())
+            {
+                self.span.record("gen_ai.response.finish_reasons", reason);
+            }
+            if let Some(usage) = &chunk.usage {
+                self.record_usage(usage, chunk.compression.as_ref());
+            }
+        }
+
+        /// Record the token usage and compression savings of a request
+        pub(crate) fn record_usage(&self, usage: &Usage, compression: Option<&Compression>) {
+            self.span
+                .record("gen_ai.usage.input_tokens", usage.prompt_tokens);
+            self.span
+                .record("gen_ai.usage.output_tokens", usage.completion_tokens);
+            if let Some(compression) = compression {
+                self.span
+                    .record("edgee.compression.saved_tokens", compression.saved_tokens);
+                self.span.record("edgee.compression.rate", compression.rate);
+            }
+        }
+
+        /// Record the failure of the request, returning the error
+        pub(crate) fn record_error(&self, error: Error) -> Error {
+            self.span.record("otel.status_code", "ERROR");
+            self.span.record("error.type", error_type(&error));
+            if let Some(status) = error.status() {
+                self.span.record("http.response.status_code", status);
+            }
+            error
+        }
+    }
+
+    impl Drop for RequestSpan {
+        fn drop(&mut self) {
+            self.span
+                .record("edgee.latency_ms", millis(self.start.elapsed()));
+        }
+    }
+
+    /// Record the status of an HTTP response in the current request span
+    pub(crate) fn record_status(status: u16) {
+        Span::current().record("http.response.status_code", status);
+    }
+
+    /// Report an HTTP exchange with the gateway
+    pub(crate) fn exchange(
+        method: &reqwest::Method,
+        path: &str,
+        status: Option<u16>,
+        latency: Duration,
+    ) {
+        tracing::debug!(
+            target: "edgee",
+            method = %method,
+            path,
+            status,
+            latency_ms = millis(latency),
+            "gateway request"
+        );
+    }
+
+    /// Report that a failed attempt is about to be retried
+    pub(crate) fn retrying(attempt: u32, delay: Duration, reason: &dyn Display) {
+        Span::current().record("edgee.attempts", attempt + 1);
+        tracing::warn!(
+            target: "edgee",
+            attempt,
+            delay_ms = millis(delay),
+            "retrying request after {}",
+            reason
+        );
+    }
+
+    /// Report a malformed stream chunk skipped in lenient mode
+    #[cfg(feature = "stream")]
+    pub(crate) fn skipped_chunk(error: &serde_json::Error, data: &str) {
+        tracing::warn!(target: "edgee", %error, data, "skipping malformed stream chunk");
+    }
+
+    /// Value of the `error.type` field
+    fn error_type(error: &Error) -> &'static str {
+        match error {
+            Error::Http(_) => "http",
+            Error::Timeout(_) => "timeout",
+            Error::Json(_) => "json",
+            Error::MissingApiKey => "missing_api_key",
+            Error::Api(_) => "api",
+            Error::Stream { .. } => "stream",
+            Error::StructuredOutput { .. } => "structured_output",
+            Error::ToolArguments { .. } => "tool_arguments",
+            Error::EmptyResponse(_) => "empty_response",
+            Error::MaxIterations { .. } => "max_iterations",
+            Error::InvalidConfig(_) => "invalid_config",
+        }
+    }
+
+    fn millis(duration: Duration) -> u64 {
+        u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use crate::{
+        error::Error,
+        models::{Compression, SendResponse, Usage},
+    };
+    use std::{fmt::Display, future::Future, time::Duration};
+
+    /// Span of a single gateway call, a no-op without the `tracing` feature
+    pub(crate) struct RequestSpan;
+
+    impl RequestSpan {
+        #[inline]
+        pub(crate) fn new(_operation: &str, _model: &str, _tags: &[String], _stream: bool) -> Self {
+            Self
+        }
+
+        #[inline]
+        pub(crate) fn instrument<F: Future>(&self, future: F) -> F {
+            future
+        }
+
+        #[cfg(feature = "blocking")]
+        #[inline]
+        pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+            f()
+        }
+
+        #[inline]
+        pub(crate) fn record_response(&self, _response: &SendResponse) {}
+
+        #[cfg(feature = "stream")]
+        #[inline]
+        pub(crate) fn record_chunk(&mut self, _chunk: &crate::models::StreamChunk) {}
+
+        #[inline]
+        pub(crate) fn record_usage(&self, _usage: &Usage, _compression: Option<&Compression>) {}
+
+        #[inline]
+        pub(crate) fn record_error(&self, error: Error) -> Error {
+            error
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[inline]
+    pub(crate) fn record_status(_status: u16) {}
+
+    #[inline]
+    pub(crate) fn retrying(_attempt: u32, _delay: Duration, _reason: &dyn Display) {}
+
+    #[cfg(feature = "stream")]
+    #[inline]
+    pub(crate) fn skipped_chunk(_error: &serde_json::Error, _data: &str) {}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use crate::{Edgee, EdgeeConfig, InputObject, Message, RetryPolicy};
+    use std::{
+        collections::HashMap,
+        fmt::Debug,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, Layer};
+
+    /// Layer recording the fields of the request spans
+    #[derive(Clone, Default)]
+    struct Recorder {
+        fields: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    impl Recorder {
+        fn field(&self, name: &str) -> Option<String> {
+            self.fields.lock().unwrap().get(name).cloned()
+        }
+    }
+
+    impl Visit for &Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            let value = format!("{:?}", value).trim_matches('"').to_string();
+            self.fields
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), value);
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_new_span(&self, attributes: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            attributes.record(&mut &*self);
+        }
+
+        fn on_record(&self, _: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            values.record(&mut &*self);
+        }
+    }
+
+    fn subscribe(recorder: &Recorder) -> tracing::subscriber::DefaultGuard {
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()))
+    }
+
+    fn client(server: &mockito::Server) -> Edgee {
+        Edgee::new(
+            EdgeeConfig::new("test-key")
+                .with_base_url(server.url())
+                .with_retry(RetryPolicy::new().with_base_delay(Duration::from_millis(1))),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_span() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(
+                serde_json::json!({
+                    "id": "resp-1",
+                    "object": "chat.completion",
+                    "created": 0,
+                    "model": "gpt-4o-2024-08-06",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+                    "usage": {"prompt_tokens": 10, "completion_tokens": 2, "total_tokens": 12},
+                    "compression": {"input_tokens": 20, "saved_tokens": 10, "rate": 0.5}
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let recorder = Recorder::default();
+        let _guard = subscribe(&recorder);
+        let input = InputObject::new(vec![Message::user("Hi")])
+            .with_tags(vec!["search".to_string(), "beta".to_string()]);
+        client(&server).send("gpt-4o", input).await.unwrap();
+
+        let field = |name| recorder.field(name).unwrap_or_default();
+        assert_eq!(field("otel.name"), "chat gpt-4o");
+        assert_eq!(field("gen_ai.operation.name"), "chat");
+        assert_eq!(field("gen_ai.request.model"), "gpt-4o");
+        assert_eq!(field("gen_ai.response.model"), "gpt-4o-2024-08-06");
+        assert_eq!(field("gen_ai.response.finish_reasons"), "stop");
+        assert_eq!(field("gen_ai.usage.input_tokens"), "10");
+        assert_eq!(field("gen_ai.usage.output_tokens"), "2");
+        assert_eq!(field("edgee.compression.saved_tokens"), "10");
+        assert_eq!(field("edgee.tags"), "search,beta");
+        assert_eq!(field("edgee.attempts"), "2");
+        assert_eq!(field("http.response.status_code"), "200");
+        assert!(recorder.field("edgee.latency_ms").is_some());
+        assert!(recorder.field("error.type").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_error_span() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(401)
+            .with_body(r#"{"error": {"message": "Invalid API key"}}"#)
+            .create_async()
+            .await;
+
+        let recorder = Recorder::default();
+        let _guard = subscribe(&recorder);
+        client(&server).send("gpt-4o", "Hi").await.unwrap_err();
+
+        assert_eq!(recorder.field("otel.status_code").unwrap(), "ERROR");
+        assert_eq!(recorder.field("error.type").unwrap(), "api");
+        assert_eq!(recorder.field("http.response.status_code").unwrap(), "401");
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_stream_span() {
+        use futures::StreamExt;
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",",
+                "\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",",
+                "\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":1,\"total_tokens\":6}}\n\n",
+                "data: [DONE]\n\n"
+            ))
+            .create_async()
+            .await;
+
+        let recorder = Recorder::default();
+        let _guard = subscribe(&recorder);
+        let stream = client(&server).stream("gpt-4o", "Hi").await.unwrap();
+        assert!(recorder.field("edgee.time_to_first_token_ms").is_none());
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 2);
+
+        assert_eq!(recorder.field("edgee.stream").unwrap(), "true");
+        assert_eq!(
+            recorder.field("gen_ai.response.finish_reasons").unwrap(),
+            "stop"
+        );
+        assert_eq!(recorder.field("gen_ai.usage.input_tokens").unwrap(), "5");
+        assert!(recorder.field("edgee.time_to_first_token_ms").is_some());
+        assert!(recorder.field("edgee.latency_ms").is_some());
+    }
+}
@@ -0,0 +1,582 @@
+//! Record and replay of gateway exchanges
+
+use crate::{
+    error::{Error, Result},
+    middleware::{Body, BoxFuture, Middleware, Next, Request, Response},
+};
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Version of the cassette file format
+const FORMAT_VERSION: u32 = 1;
+
+/// Replacement of the secrets in recorded exchanges
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether a cassette records or replays exchanges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Send requests to the gateway and record the exchanges
+    Record,
+    /// Answer requests from the recorded exchanges, without any network call
+    Replay,
+}
+
+/// Part of a request compared when looking for a recorded exchange
+///
+/// The method, the path and whether the response is streamed are always
+/// compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    /// The `model` field of the body
+    Model,
+    /// The `messages` field of the body
+    Messages,
+    /// The whole body
+    Body,
+}
+
+/// Request of a recorded exchange
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path of the URL, without the base URL
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+/// Response of a recorded exchange
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Raw body, including the `data:` lines of streamed responses
+    pub body: String,
+}
+
+impl RecordedResponse {
+    /// Rebuild the response
+    ///
+    /// Streamed bodies are replayed one event per chunk.
+    fn to_response(&self) -> Response {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::try_from(name.as_str()),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+
+        let is_event_stream = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+        let body = if is_event_stream {
+            let events: Vec<Result<Bytes>> = self
+                .body
+                .split_inclusive("\n\n")
+                .map(|event| Ok(Bytes::copy_from_slice(event.as_bytes())))
+                .collect();
+            Body::from_stream(futures::stream::iter(events))
+        } else {
+            Body::from(self.body.clone())
+        };
+
+        let mut response = Response::new(self.status, body);
+        response.headers = headers;
+        response
+    }
+}
+
+/// Recorded exchange with the gateway
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// Content of a cassette file
+#[derive(Serialize, Deserialize)]
+struct CassetteFile {
+    version: u32,
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Default)]
+struct State {
+    interactions: Vec<Interaction>,
+    /// Whether each interaction has been replayed
+    played: Vec<bool>,
+}
+
+/// Middleware recording gateway exchanges to a JSON file, or replaying them
+///
+/// In record mode, every exchange is sent to the gateway and appended to the
+/// cassette file, which is rewritten after each exchange. Streamed responses
+/// are buffered before being returned, and recorded as raw SSE text. The API
+/// key of the configuration is replaced by `[REDACTED]` wherever it appears,
+/// and request headers are not recorded.
+///
+/// In replay mode, each request is answered by the first interaction not
+/// played yet that matches it (see [`Match`]), and fails with
+/// [`Error::InvalidConfig`] when there is none. No request reaches the
+/// network, so any API key and base URL can be used.
+///
+/// Clones share the same interactions.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::testing::Cassette;
+/// use edgee::{Edgee, EdgeeConfig};
+///
+/// // Records on the first run, when the file does not exist, then replays
+/// let cassette = Cassette::auto("tests/cassettes/capital.json")?;
+/// let api_key = std::env::var("EDGEE_API_KEY").unwrap_or_default();
+/// let client = Edgee::new(EdgeeConfig::new(api_key).with_middleware(cassette));
+///
+/// let response = client.send("gpt-4o", "What is the capital of France?").await?;
+/// assert!(response.text().unwrap_or("").contains("Paris"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    matchers: Vec<Match>,
+    secrets: Vec<String>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Cassette {
+    /// Record exchanges to the given file, replacing its content
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self::with_mode(path.into(), CassetteMode::Record, State::default())
+    }
+
+    /// Replay the exchanges recorded in the given file
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file: CassetteFile = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+            .map_err(|e| cassette_error(&path, "cannot read", e))?;
+        if file.version != FORMAT_VERSION {
+            return Err(cassette_error(
+                &path,
+                "cannot read",
+                format!("unsupported version {}", file.version),
+            ));
+        }
+
+        let state = State {
+            played: vec![false; file.interactions.len()],
+            interactions: file.interactions,
+        };
+        Ok(Self::with_mode(path, CassetteMode::Replay, state))
+    }
+
+    /// Replay the given file if it exists, record it otherwise
+    ///
+    /// Delete the file to record the exchanges again.
+    pub fn auto(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            Self::replay(path)
+        } else {
+            Ok(Self::record(path))
+        }
+    }
+
+    fn with_mode(path: PathBuf, mode: CassetteMode, state: State) -> Self {
+        Self {
+            path,
+            mode,
+            matchers: vec![Match::Model, Match::Messages],
+            secrets: Vec::new(),
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Set the parts of the requests compared when replaying
+    /// (default: model and messages)
+    pub fn with_matchers(mut self, matchers: impl IntoIterator<Item = Match>) -> Self {
+        self.matchers = matchers.into_iter().collect();
+        self
+    }
+
+    /// Redact another secret from the recorded exchanges
+    pub fn with_redaction(mut self, secret: impl Into<String>) -> Self {
+        self.secrets.push(secret.into());
+        self
+    }
+
+    /// Mode of the cassette
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// Path of the cassette file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Recorded interactions
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.lock().interactions.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn record_exchange(&self, request: Request, next: Next<'_>) -> Result<Response> {
+        let mut secrets = self.secrets.clone();
+        if let Some(api_key) = request
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        {
+            secrets.push(api_key.to_string());
+        }
+        secrets.retain(|secret| !secret.is_empty());
+
+        let recorded_request = RecordedRequest {
+            method: request.method.to_string(),
+            path: request.path().to_string(),
+            body: request
+                .body
+                .as_ref()
+                .map(|body| redact_value(body, &secrets)),
+        };
+
+        let response = next.run(request).await?;
+        let bytes = response.body.bytes().await?;
+        let recorded_response = RecordedResponse {
+            status: response.status,
+            headers: response
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), redact(value.to_str().ok()?, &secrets)))
+                })
+                .collect(),
+            body: redact(&String::from_utf8_lossy(&bytes), &secrets),
+        };
+
+        {
+            let mut state = self.lock();
+            state.interactions.push(Interaction {
+                request: recorded_request,
+                response: recorded_response,
+            });
+            state.played.push(true);
+            self.save(&state.interactions)?;
+        }
+
+        Ok(Response {
+            status: response.status,
+            headers: response.headers,
+            body: Body::from(bytes),
+            extensions: response.extensions,
+        })
+    }
+
+    fn play(&self, request: &Request) -> Result<Response> {
+        let mut state = self.lock();
+        let State {
+            interactions,
+            played,
+        } = &mut *state;
+        let index = (0..interactions.len())
+            .find(|&i| !played[i] && self.matches(&interactions[i].request, request))
+            .ok_or_else(|| {
+                cassette_error(
+                    &self.path,
+                    "no interaction left in",
+                    format!("for {} {}", request.method, request.path()),
+                )
+            })?;
+
+        played[index] = true;
+        Ok(interactions[index].response.to_response())
+    }
+
+    fn matches(&self, recorded: &RecordedRequest, request: &Request) -> bool {
+        let field = |body: Option<&Value>, name| body.and_then(|b: &Value| b.get(name).cloned());
+        let is_stream =
+            |body: Option<&Value>| field(body, "stream").and_then(|v| v.as_bool()) == Some(true);
+
+        let body = request.body.as_ref();
+        let recorded_body = recorded.body.as_ref();
+        recorded.method == request.method.as_str()
+            && recorded.path == request.path()
+            && is_stream(recorded_body) == is_stream(body)
+            && self.matchers.iter().all(|matcher| match matcher {
+                Match::Model => field(recorded_body, "model") == field(body, "model"),
+                Match::Messages => field(recorded_body, "messages") == field(body, "messages"),
+                Match::Body => recorded_body == body,
+            })
+    }
+
+    fn save(&self, interactions: &[Interaction]) -> Result<()> {
+        let file = CassetteFile {
+            version: FORMAT_VERSION,
+            interactions: interactions.to_vec(),
+        };
+        let mut data = serde_json::to_string_pretty(&file)?;
+        data.push('\n');
+
+        let dir = self.path.parent().filter(|p| !p.as_os_str().is_empty());
+        dir.map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&self.path, data))
+            .map_err(|e| cassette_error(&self.path, "cannot write", e))
+    }
+}
+
+impl std::fmt::Debug for Cassette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cassette")
+            .field("path", &self.path)
+            .field("mode", &self.mode)
+            .field("matchers", &self.matchers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Middleware for Cassette {
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.mode {
+                CassetteMode::Record => self.record_exchange(request, next).await,
+                CassetteMode::Replay => self.play(&request),
+            }
+        })
+    }
+}
+
+fn cassette_error(path: &Path, action: &str, detail: impl std::fmt::Display) -> Error {
+    Error::InvalidConfig(format!(
+        "{} cassette {}: {}",
+        action,
+        path.display(),
+        detail
+    ))
+}
+
+fn redact(text: &str, secrets: &[String]) -> String {
+    secrets.iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret.as_str(), REDACTED)
+    })
+}
+
+fn redact_value(value: &Value, secrets: &[String]) -> Value {
+    match value {
+        Value::String(s) => Value::String(redact(s, secrets)),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|v| redact_value(v, secrets)).collect())
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, v)| (name.clone(), redact_value(v, secrets)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Edgee, EdgeeConfig, InputObject, Message};
+    use serde_json::json;
+
+    fn cassette_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("edgee-cassettes-{}", std::process::id()))
+            .join(format!("{}.json", name))
+    }
+
+    fn client(base_url: &str, api_key: &str, cassette: &Cassette) -> Edgee {
+        Edgee::new(
+            EdgeeConfig::new(api_key)
+                .with_base_url(base_url)
+                .with_middleware(cassette.clone()),
+        )
+    }
+
+    /// Client of a replay, whose requests would fail if they reached the network
+    fn offline_client(cassette: &Cassette) -> Edgee {
+        client("http://127.0.0.1:1", "test-key", cassette)
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": "Paris"}, "finish_reason": "stop"}]
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let path = cassette_path("send");
+        let cassette = Cassette::auto(&path).unwrap();
+        assert_eq!(cassette.mode(), CassetteMode::Record);
+        let recorded = client(&server.url(), "secret-api-key", &cassette)
+            .send("gpt-4o", "Capital of France?")
+            .await
+            .unwrap();
+        assert_eq!(recorded.text(), Some("Paris"));
+        mock.assert_async().await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("secret-api-key"));
+        assert_eq!(
+            cassette.interactions()[0].request.path,
+            "/v1/chat/completions"
+        );
+
+        let cassette = Cassette::auto(&path).unwrap();
+        assert_eq!(cassette.mode(), CassetteMode::Replay);
+        let client = offline_client(&cassette);
+        let replayed = client.send("gpt-4o", "Capital of France?").await.unwrap();
+        assert_eq!(replayed.text(), Some("Paris"));
+
+        // Each interaction is replayed once
+        let error = client
+            .send("gpt-4o", "Capital of France?")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_replay_stream() {
+        use futures::StreamExt;
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_body(concat!(
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",",
+                "\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"},\"finish_reason\":null}]}\n\n",
+                "data: {\"id\":\"1\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"gpt-4o\",",
+                "\"choices\":[{\"index\":0,\"delta\":{\"content\":\" world\"},\"finish_reason\":\"stop\"}]}\n\n",
+                "data: [DONE]\n\n"
+            ))
+            .create_async()
+            .await;
+
+        let path = cassette_path("stream");
+        let cassette = Cassette::record(&path);
+        let stream = client(&server.url(), "secret-api-key", &cassette)
+            .stream("gpt-4o", "Hi")
+            .await
+            .unwrap();
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 2);
+
+        let cassette = Cassette::replay(&path).unwrap();
+        let client = offline_client(&cassette);
+        // A non-streaming request does not match a recorded stream
+        assert!(client.send("gpt-4o", "Hi").await.is_err());
+
+        let texts: Vec<String> = client
+            .stream("gpt-4o", "Hi")
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap().text().unwrap_or_default().to_string())
+            .collect()
+            .await;
+        assert_eq!(texts, ["Hello", " world"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_matchers() {
+        let interaction = |content: &str, reply: &str| {
+            Interaction {
+            request: RecordedRequest {
+                method: "POST".to_string(),
+                path: "/v1/chat/completions".to_string(),
+                body: Some(json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "user", "content": content}],
+                    "temperature": 0.5,
+                    "stream": false
+                })),
+            },
+            response: RecordedResponse {
+                status: 200,
+                headers: BTreeMap::new(),
+                body: json!({
+                    "id": "1", "object": "chat.completion", "created": 0, "model": "gpt-4o",
+                    "choices": [{"index": 0, "message": {"role": "assistant", "content": reply}, "finish_reason": "stop"}]
+                })
+                .to_string(),
+            },
+        }
+        };
+        let path = cassette_path("matchers");
+        let file = CassetteFile {
+            version: FORMAT_VERSION,
+            interactions: vec![
+                interaction("France?", "Paris"),
+                interaction("Italy?", "Rome"),
+            ],
+        };
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+
+        let client = offline_client(&Cassette::replay(&path).unwrap());
+        let italy = client.send("gpt-4o", "Italy?").await.unwrap();
+        assert_eq!(italy.text(), Some("Rome"));
+        assert!(client.send("gpt-4o-mini", "France?").await.is_err());
+        assert!(client.send("gpt-4o", "Spain?").await.is_err());
+
+        let client = offline_client(
+            &Cassette::replay(&path)
+                .unwrap()
+                .with_matchers([Match::Body]),
+        );
+        assert!(client.send("gpt-4o", "France?").await.is_err());
+        let input = InputObject::new(vec![Message::user("France?")]).with_temperature(0.5);
+        let france = client.send("gpt-4o", input).await.unwrap();
+        assert_eq!(france.text(), Some("Paris"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_redact() {
+        let secrets = vec!["sk-123".to_string()];
+        let value = json!({"key": "sk-123", "list": ["Bearer sk-123", 1]});
+        assert_eq!(
+            redact_value(&value, &secrets),
+            json!({"key": "[REDACTED]", "list": ["Bearer [REDACTED]", 1]})
+        );
+    }
+}
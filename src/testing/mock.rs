@@ -0,0 +1,570 @@
+//! In-process fake gateway
+
+use crate::{
+    models::{EdgeeConfig, Message, SendResponse, StreamChunk, ToolCall},
+    retry::RetryPolicy,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::{JoinHandle, JoinSet},
+};
+
+/// Model of the scripted replies
+const MOCK_MODEL: &str = "mock";
+
+/// Request received by a [`MockGateway`]
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub method: String,
+    /// Path of the URL, without the query string
+    pub path: String,
+    /// Headers, with lowercase names
+    pub headers: BTreeMap<String, String>,
+    /// JSON body, if any
+    pub body: Option<Value>,
+}
+
+impl ReceivedRequest {
+    /// Get a header value
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// Get the requested model
+    pub fn model(&self) -> Option<&str> {
+        self.body.as_ref()?.get("model")?.as_str()
+    }
+
+    /// Whether a streamed response was requested
+    pub fn is_stream(&self) -> bool {
+        self.body
+            .as_ref()
+            .and_then(|b| b.get("stream"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// Get the messages of a chat completion request
+    pub fn messages(&self) -> Vec<Message> {
+        self.body
+            .as_ref()
+            .and_then(|b| b.get("messages"))
+            .and_then(|m| serde_json::from_value(m.clone()).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MockBody {
+    Json(Value),
+    /// Server-sent events, each including its trailing blank line
+    Events(Vec<String>),
+}
+
+/// Scripted reply of a [`MockGateway`]
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: MockBody,
+    delay: Duration,
+    disconnect_after: Option<usize>,
+}
+
+impl MockResponse {
+    /// Reply with a JSON body
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: MockBody::Json(body),
+            delay: Duration::ZERO,
+            disconnect_after: None,
+        }
+    }
+
+    /// Reply with a complete response
+    pub fn send(response: &SendResponse) -> Self {
+        Self::json(200, serde_json::to_value(response).unwrap_or_default())
+    }
+
+    /// Reply with an assistant message
+    pub fn text(content: impl Into<String>) -> Self {
+        Self::message(
+            json!({"role": "assistant", "content": content.into()}),
+            "stop",
+        )
+    }
+
+    /// Reply with a call of the given tool
+    pub fn tool_call(name: impl Into<String>, arguments: Value) -> Self {
+        Self::tool_calls(vec![ToolCall {
+            id: "call_1".to_string(),
+            call_type: "function".to_string(),
+            function: crate::models::FunctionCall {
+                name: name.into(),
+                arguments: arguments.to_string(),
+            },
+        }])
+    }
+
+    /// Reply with the given tool calls
+    pub fn tool_calls(tool_calls: Vec<ToolCall>) -> Self {
+        Self::message(
+            json!({"role": "assistant", "tool_calls": tool_calls}),
+            "tool_calls",
+        )
+    }
+
+    fn message(message: Value, finish_reason: &str) -> Self {
+        Self::json(
+            200,
+            json!({
+                "id": "mock-response",
+                "object": "chat.completion",
+                "created": 0,
+                "model": MOCK_MODEL,
+                "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}]
+            }),
+        )
+    }
+
+    /// Reply with an error in the format of the gateway
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(
+            status,
+            json!({"error": {"message": message.into(), "type": "mock_error"}}),
+        )
+    }
+
+    /// Stream the given chunks, followed by `[DONE]`
+    pub fn stream(chunks: impl IntoIterator<Item = StreamChunk>) -> Self {
+        Self::events(
+            chunks
+                .into_iter()
+                .map(|chunk| serde_json::to_value(chunk).unwrap_or_default()),
+        )
+    }
+
+    /// Stream an assistant message, one chunk per part
+    pub fn stream_text<S: Into<String>>(parts: impl IntoIterator<Item = S>) -> Self {
+        let parts: Vec<String> = parts.into_iter().map(Into::into).collect();
+        let count = parts.len();
+        Self::events(parts.into_iter().enumerate().map(|(i, content)| {
+            let delta = if i == 0 {
+                json!({"role": "assistant", "content": content})
+            } else {
+                json!({"content": content})
+            };
+            let finish_reason = (i + 1 == count).then_some("stop");
+            json!({
+                "id": "mock-response",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": MOCK_MODEL,
+                "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}]
+            })
+        }))
+    }
+
+    fn events(chunks: impl Iterator<Item = Value>) -> Self {
+        let mut events: Vec<String> = chunks.map(|c| format!("data: {}\n\n", c)).collect();
+        events.push("data: [DONE]\n\n".to_string());
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: MockBody::Events(events),
+            delay: Duration::ZERO,
+            disconnect_after: None,
+        }
+    }
+
+    /// Add a header to the reply
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Wait before replying, and between the events of a stream
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Close the connection after the given number of stream events
+    ///
+    /// Replies that are not streamed close the connection without any
+    /// response.
+    pub fn with_disconnect_after(mut self, events: usize) -> Self {
+        self.disconnect_after = Some(events);
+        self
+    }
+
+    /// Write the reply, returning `false` when the connection must be closed
+    async fn write_to(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<bool> {
+        match &self.body {
+            MockBody::Json(body) => {
+                tokio::time::sleep(self.delay).await;
+                if self.disconnect_after.is_some() {
+                    return Ok(false);
+                }
+                let body = body.to_string();
+                let head = self.head(
+                    "application/json",
+                    &format!("content-length: {}", body.len()),
+                );
+                writer.write_all(head.as_bytes()).await?;
+                writer.write_all(body.as_bytes()).await?;
+            }
+            MockBody::Events(events) => {
+                let head = self.head("text/event-stream", "transfer-encoding: chunked");
+                writer.write_all(head.as_bytes()).await?;
+                writer.flush().await?;
+                for (i, event) in events.iter().enumerate() {
+                    if self.disconnect_after == Some(i) {
+                        return Ok(false);
+                    }
+                    tokio::time::sleep(self.delay).await;
+                    let chunk = format!("{:x}\r\n{}\r\n", event.len(), event);
+                    writer.write_all(chunk.as_bytes()).await?;
+                    writer.flush().await?;
+                }
+                writer.write_all(b"0\r\n\r\n").await?;
+            }
+        }
+        writer.flush().await?;
+        Ok(true)
+    }
+
+    fn head(&self, content_type: &str, framing: &str) -> String {
+        let reason = http::StatusCode::from_u16(self.status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("");
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\ncontent-type: {}\r\n{}\r\n",
+            self.status, reason, content_type, framing
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        head
+    }
+}
+
+#[derive(Default)]
+struct State {
+    responses: VecDeque<MockResponse>,
+    requests: Vec<ReceivedRequest>,
+}
+
+/// Fake gateway listening on a local port
+///
+/// Scripted replies are served in order, whatever the request. Requests
+/// without a scripted reply get a 404 error. The server stops when the
+/// gateway is dropped.
+///
+/// # Example
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> edgee::Result<()> {
+/// use edgee::testing::{MockGateway, MockResponse};
+/// use edgee::Edgee;
+///
+/// let gateway = MockGateway::start().await;
+/// gateway.respond(MockResponse::text("Paris"));
+///
+/// let client = Edgee::new(gateway.config());
+/// let response = client.send("gpt-4o", "Capital of France?").await?;
+/// assert_eq!(response.text(), Some("Paris"));
+///
+/// gateway.assert_requests(1);
+/// assert_eq!(gateway.requests()[0].model(), Some("gpt-4o"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockGateway {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    server: JoinHandle<()>,
+}
+
+impl MockGateway {
+    /// Start a gateway on a free local port
+    ///
+    /// # Panics
+    ///
+    /// Panics if no local port can be bound.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind the mock gateway");
+        let addr = listener
+            .local_addr()
+            .expect("failed to bind the mock gateway");
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            // Connections are aborted with the server
+            let mut connections = JoinSet::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                connections.spawn(serve(socket, server_state.clone()));
+            }
+        });
+
+        Self {
+            addr,
+            state,
+            server,
+        }
+    }
+
+    /// Base URL of the gateway
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Client configuration pointing to the gateway
+    ///
+    /// Retries are kept, with a 1ms base delay.
+    pub fn config(&self) -> EdgeeConfig {
+        EdgeeConfig::new("test-key")
+            .with_base_url(self.url())
+            .with_retry(RetryPolicy::new().with_base_delay(Duration::from_millis(1)))
+    }
+
+    /// Script the next reply
+    pub fn respond(&self, response: MockResponse) -> &Self {
+        self.lock().responses.push_back(response);
+        self
+    }
+
+    /// Number of scripted replies not served yet
+    pub fn pending(&self) -> usize {
+        self.lock().responses.len()
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.lock().requests.clone()
+    }
+
+    /// Last request received
+    pub fn last_request(&self) -> Option<ReceivedRequest> {
+        self.lock().requests.last().cloned()
+    }
+
+    /// Assert that the given number of requests was received
+    #[track_caller]
+    pub fn assert_requests(&self, count: usize) {
+        let received = self.lock().requests.len();
+        assert_eq!(
+            received, count,
+            "expected {} requests to the mock gateway, received {}",
+            count, received
+        );
+    }
+
+    /// Assert that all the scripted replies were served
+    #[track_caller]
+    pub fn assert_done(&self) {
+        let pending = self.pending();
+        assert_eq!(
+            pending, 0,
+            "{} scripted replies of the mock gateway were not served",
+            pending
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for MockGateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockGateway")
+            .field("addr", &self.addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for MockGateway {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+/// Serve the requests of a connection until it is closed
+async fn serve(socket: TcpStream, state: Arc<Mutex<State>>) -> io::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut reader = BufReader::new(reader);
+    while let Some(request) = read_request(&mut reader).await? {
+        let response = {
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            let response = state.responses.pop_front().unwrap_or_else(|| {
+                MockResponse::error(
+                    404,
+                    format!("no scripted reply for {} {}", request.method, request.path),
+                )
+            });
+            state.requests.push(request);
+            response
+        };
+        if !response.write_to(&mut writer).await? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read an HTTP/1.1 request, or `None` when the connection is closed
+async fn read_request(
+    reader: &mut (impl AsyncBufReadExt + Unpin),
+) -> io::Result<Option<ReceivedRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut request_line = line.split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or("/");
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut headers = BTreeMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).await?;
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(ReceivedRequest {
+        method,
+        path,
+        headers,
+        body: serde_json::from_slice(&body).ok(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Edgee;
+
+    #[tokio::test]
+    async fn test_scripted_replies() {
+        let gateway = MockGateway::start().await;
+        gateway
+            .respond(MockResponse::text("Paris"))
+            .respond(MockResponse::tool_call(
+                "get_weather",
+                json!({"city": "Paris"}),
+            ))
+            .respond(MockResponse::error(400, "Invalid model"));
+        let client = Edgee::new(gateway.config());
+
+        let response = client.send("gpt-4o", "Capital of France?").await.unwrap();
+        assert_eq!(response.text(), Some("Paris"));
+
+        let response = client.send("gpt-4o", "Weather in Paris?").await.unwrap();
+        assert_eq!(response.finish_reason(), Some("tool_calls"));
+        let call = &response.tool_calls().unwrap()[0];
+        assert_eq!(call.function.name, "get_weather");
+        let arguments: Value = call.parse_arguments().unwrap();
+        assert_eq!(arguments, json!({"city": "Paris"}));
+
+        let error = client.send("unknown", "Hi").await.unwrap_err();
+        assert_eq!(error.status(), Some(400));
+        assert_eq!(error.api_error().unwrap().message, "Invalid model");
+        gateway.assert_done();
+
+        // Requests without a scripted reply are rejected
+        let error = client.send("gpt-4o", "Hi").await.unwrap_err();
+        assert_eq!(error.status(), Some(404));
+
+        gateway.assert_requests(4);
+        let request = gateway.requests().remove(1);
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/chat/completions");
+        assert_eq!(request.header("Authorization"), Some("Bearer test-key"));
+        assert_eq!(request.model(), Some("gpt-4o"));
+        assert!(!request.is_stream());
+        assert_eq!(request.messages()[0].text(), Some("Weather in Paris?"));
+    }
+
+    #[tokio::test]
+    async fn test_retried_error() {
+        let gateway = MockGateway::start().await;
+        gateway
+            .respond(MockResponse::error(503, "Overloaded").with_header("retry-after", "0"))
+            .respond(MockResponse::text("Hi"));
+
+        let response = Edgee::new(gateway.config())
+            .send("gpt-4o", "Hi")
+            .await
+            .unwrap();
+        assert_eq!(response.text(), Some("Hi"));
+        gateway.assert_requests(2);
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_stream_with_delay() {
+        use futures::StreamExt;
+
+        let gateway = MockGateway::start().await;
+        gateway.respond(
+            MockResponse::stream_text(["Hel", "lo"]).with_delay(Duration::from_millis(50)),
+        );
+
+        let start = std::time::Instant::now();
+        let texts: Vec<String> = Edgee::new(gateway.config())
+            .stream("gpt-4o", "Hi")
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap().text().unwrap_or_default().to_string())
+            .collect()
+            .await;
+        assert_eq!(texts, ["Hel", "lo"]);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(gateway.last_request().unwrap().is_stream());
+    }
+
+    #[cfg(feature = "stream")]
+    #[tokio::test]
+    async fn test_stream_disconnect() {
+        use crate::Error;
+        use futures::StreamExt;
+
+        let gateway = MockGateway::start().await;
+        gateway.respond(MockResponse::stream_text(["a", "b", "c"]).with_disconnect_after(1));
+
+        let config = gateway.config().with_retry(RetryPolicy::none());
+        let mut stream = Edgee::new(config).stream("gpt-4o", "Hi").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap().text(), Some("a"));
+        assert!(matches!(stream.next().await, Some(Err(Error::Http(_)))));
+    }
+}
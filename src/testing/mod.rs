@@ -0,0 +1,12 @@
+//! Helpers for testing code built on the SDK
+//!
+//! - [`Cassette`] records real gateway exchanges to a JSON file and replays
+//!   them offline, so tests can run deterministically without an API key.
+//! - [`MockGateway`] is a fake gateway on a local port, serving scripted
+//!   replies and recording the requests it receives.
+
+mod cassette;
+mod mock;
+
+pub use cassette::{Cassette, CassetteMode, Interaction, Match, RecordedRequest, RecordedResponse};
+pub use mock::{MockGateway, MockResponse, ReceivedRequest};
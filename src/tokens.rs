@@ -0,0 +1,371 @@
+use crate::models::{Content, ContentPart, ImageDetail, InputObject, Message};
+
+/// Tokens added to every message for the message framing
+const TOKENS_PER_MESSAGE: usize = 3;
+
+/// Tokens of the role of a message: every role name is a single token in
+/// the OpenAI encodings
+const TOKENS_PER_ROLE: usize = 1;
+
+/// Tokens priming the assistant reply, added once per request by [`estimate`]
+const TOKENS_PER_REPLY: usize = 3;
+
+/// Fixed cost of a low detail image
+const LOW_DETAIL_IMAGE_TOKENS: usize = 85;
+
+/// Cost of an image with the `auto` or `high` detail, for a typical 1024x1024 image
+const HIGH_DETAIL_IMAGE_TOKENS: usize = 765;
+
+/// Context window and output limits of a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelLimits {
+    /// Model name, or prefix shared by the model versions
+    pub model: &'static str,
+    /// Maximum number of tokens of the prompt and the completion together
+    pub context_window: u32,
+    /// Maximum number of tokens the model can generate
+    pub max_output_tokens: u32,
+}
+
+const fn limits(model: &'static str, context_window: u32, max_output_tokens: u32) -> ModelLimits {
+    ModelLimits {
+        model,
+        context_window,
+        max_output_tokens,
+    }
+}
+
+/// Known model limits, matched by name followed by a version suffix
+const MODEL_LIMITS: &[ModelLimits] = &[
+    // OpenAI
+    limits("gpt-5", 400_000, 128_000),
+    limits("gpt-5-mini", 400_000, 128_000),
+    limits("gpt-5-nano", 400_000, 128_000),
+    limits("gpt-4.5", 128_000, 16_384),
+    limits("gpt-4.1", 1_047_576, 32_768),
+    limits("gpt-4.1-mini", 1_047_576, 32_768),
+    limits("gpt-4.1-nano", 1_047_576, 32_768),
+    limits("gpt-4o", 128_000, 16_384),
+    limits("gpt-4o-mini", 128_000, 16_384),
+    limits("chatgpt-4o", 128_000, 16_384),
+    limits("gpt-4-turbo", 128_000, 4_096),
+    limits("gpt-4-1106-preview", 128_000, 4_096),
+    limits("gpt-4-0125-preview", 128_000, 4_096),
+    limits("gpt-4-32k", 32_768, 32_768),
+    limits("gpt-4", 8_192, 8_192),
+    limits("gpt-3.5-turbo", 16_385, 4_096),
+    limits("o1", 200_000, 100_000),
+    limits("o1-mini", 128_000, 65_536),
+    limits("o1-pro", 200_000, 100_000),
+    limits("o3", 200_000, 100_000),
+    limits("o3-mini", 200_000, 100_000),
+    limits("o3-pro", 200_000, 100_000),
+    limits("o4-mini", 200_000, 100_000),
+    // Anthropic
+    limits("claude-opus-4", 200_000, 32_000),
+    limits("claude-sonnet-4", 200_000, 64_000),
+    limits("claude-haiku-4", 200_000, 64_000),
+    limits("claude-3-7-sonnet", 200_000, 64_000),
+    limits("claude-3-5-sonnet", 200_000, 8_192),
+    limits("claude-3-5-haiku", 200_000, 8_192),
+    limits("claude-3-opus", 200_000, 4_096),
+    limits("claude-3-sonnet", 200_000, 4_096),
+    limits("claude-3-haiku", 200_000, 4_096),
+    // Google
+    limits("gemini-2.5-pro", 1_048_576, 65_536),
+    limits("gemini-2.5-flash", 1_048_576, 65_536),
+    limits("gemini-2.5-flash-lite", 1_048_576, 65_536),
+    limits("gemini-2.0-flash", 1_048_576, 8_192),
+    limits("gemini-2.0-flash-lite", 1_048_576, 8_192),
+    limits("gemini-1.5-pro", 2_097_152, 8_192),
+    limits("gemini-1.5-flash", 1_048_576, 8_192),
+    // Mistral
+    limits("mistral-large", 131_072, 131_072),
+    limits("mistral-medium", 131_072, 131_072),
+    limits("mistral-small", 32_768, 32_768),
+    limits("codestral", 256_000, 256_000),
+    // Meta
+    limits("llama-3.3-70b-instruct", 131_072, 8_192),
+    limits("llama-3.1-8b-instruct", 131_072, 8_192),
+    limits("llama-3.1-70b-instruct", 131_072, 8_192),
+    limits("llama-3.1-405b-instruct", 131_072, 8_192),
+];
+
+/// Look up the limits of a model
+///
+/// Provider prefixes such as `openai/` are ignored. A known model matches
+/// when the name is followed by nothing or by a date or version suffix only,
+/// and the longest match wins, so `gpt-4o-2024-08-06` matches `gpt-4o` but
+/// `gpt-4o-audio` is unknown and `o1-mini` does not match `o1`.
+pub fn model_limits(model: &str) -> Option<&'static ModelLimits> {
+    let model = strip_provider(model);
+    MODEL_LIMITS
+        .iter()
+        .filter(|limits| is_model_version(model, limits.model))
+        .max_by_key(|limits| limits.model.len())
+}
+
+/// Count the tokens of a text for the given model
+///
+/// With the `tokenizer` feature, OpenAI models are counted exactly with
+/// their BPE encoding. Other models, or all models without the feature,
+/// use an estimate of 4 characters per token.
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    #[cfg(feature = "tokenizer")]
+    if let Some(bpe) = encoding(model) {
+        return bpe.encode_with_special_tokens(text).len();
+    }
+
+    #[cfg(not(feature = "tokenizer"))]
+    let _ = model;
+
+    text.chars().count().div_ceil(4)
+}
+
+/// Estimate the prompt tokens of a list of messages
+///
+/// Follows OpenAI's counting recipe: each message costs its framing and
+/// role tokens plus the tokens of its content. The tokens priming the reply
+/// are not included, as they are added once per request by [`estimate`].
+pub fn count_message_tokens<'a>(
+    model: &str,
+    messages: impl IntoIterator<Item = &'a Message>,
+) -> usize {
+    messages
+        .into_iter()
+        .map(|message| {
+            let mut tokens = TOKENS_PER_MESSAGE + TOKENS_PER_ROLE;
+            match &message.content {
+                Some(Content::Text(text)) => tokens += count_tokens(model, text),
+                Some(Content::Parts(parts)) => {
+                    tokens += parts
+                        .iter()
+                        .map(|part| count_part(model, part))
+                        .sum::<usize>()
+                }
+                None => {}
+            }
+            for call in message.tool_calls.iter().flatten() {
+                tokens += count_tokens(model, &call.function.name);
+                tokens += count_tokens(model, &call.function.arguments);
+            }
+            if let Some(id) = &message.tool_call_id {
+                tokens += count_tokens(model, id);
+            }
+            tokens
+        })
+        .sum()
+}
+
+/// Estimate the prompt tokens of a request
+///
+/// Counts the messages, the tokens priming the reply and the tool
+/// definitions. Images are counted at a fixed cost depending on their
+/// detail level. Audio and file parts are
+/// not counted.
+///
+/// # Example
+/// ```
+/// use edgee::{tokens, InputObject, Message};
+///
+/// let input = InputObject::new(vec![Message::user("Summarize this report")]);
+/// let estimate = tokens::estimate("gpt-4o", &input);
+///
+/// if !estimate.fits() {
+///     println!("{} tokens is too large for gpt-4o", estimate.prompt_tokens);
+/// }
+/// ```
+pub fn estimate(model: &str, input: &InputObject) -> TokenEstimate {
+    let mut prompt_tokens = count_message_tokens(model, &input.messages) + TOKENS_PER_REPLY;
+    if let Some(tools) = input.tools.as_ref().filter(|tools| !tools.is_empty()) {
+        let definitions = serde_json::to_string(tools).unwrap_or_default();
+        prompt_tokens += count_tokens(model, &definitions);
+    }
+
+    TokenEstimate {
+        prompt_tokens,
+        max_output_tokens: input.max_completion_tokens.or(input.max_tokens),
+        limits: model_limits(model),
+    }
+}
+
+/// Token estimate of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenEstimate {
+    /// Estimated number of prompt tokens
+    pub prompt_tokens: usize,
+    /// Output tokens requested with `max_tokens` or `max_completion_tokens`
+    pub max_output_tokens: Option<u32>,
+    /// Limits of the model, if known
+    pub limits: Option<&'static ModelLimits>,
+}
+
+impl TokenEstimate {
+    /// Tokens left for the completion, if the model limits are known
+    pub fn remaining(&self) -> Option<usize> {
+        self.limits
+            .map(|limits| (limits.context_window as usize).saturating_sub(self.prompt_tokens))
+    }
+
+    /// Whether the prompt and the requested output fit the context window
+    ///
+    /// Always true when the model limits are unknown.
+    pub fn fits(&self) -> bool {
+        let output = self.max_output_tokens.unwrap_or(0) as usize;
+        self.remaining()
+            .map_or(true, |remaining| remaining > 0 && remaining >= output)
+    }
+}
+
+/// Estimate the tokens of a content part
+fn count_part(model: &str, part: &ContentPart) -> usize {
+    match part {
+        ContentPart::Text { text } => count_tokens(model, text),
+        ContentPart::ImageUrl { image_url } => match image_url.detail {
+            Some(ImageDetail::Low) => LOW_DETAIL_IMAGE_TOKENS,
+            _ => HIGH_DETAIL_IMAGE_TOKENS,
+        },
+        ContentPart::InputAudio { .. } | ContentPart::File { .. } => 0,
+    }
+}
+
+/// Remove the provider prefix of a model name, such as `openai/`
+pub(crate) fn strip_provider(model: &str) -> &str {
+    model.rsplit_once('/').map_or(model, |(_, name)| name)
+}
+
+/// Whether `model` is `name`, or `name` followed by a version suffix
+pub(crate) fn is_model_version(model: &str, name: &str) -> bool {
+    model
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || is_version_suffix(rest))
+}
+
+/// Whether the end of a model name only identifies a version of the model
+/// (e.g. `-2024-08-06`, `-20250514`, `-0613`, `-latest`, `-preview`)
+fn is_version_suffix(suffix: &str) -> bool {
+    suffix.strip_prefix('-').is_some_and(|suffix| {
+        suffix.split('-').all(|part| {
+            let version = part.strip_prefix('v').unwrap_or(part);
+            (!version.is_empty() && version.chars().all(|c| c.is_ascii_digit()))
+                || part == "latest"
+                || part == "preview"
+        })
+    })
+}
+
+/// BPE encoding of an OpenAI model
+#[cfg(feature = "tokenizer")]
+fn encoding(model: &str) -> Option<&'static tiktoken_rs::CoreBPE> {
+    use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton};
+
+    let model = strip_provider(model);
+    let o200k = ["gpt-5", "gpt-4.1", "gpt-4o", "chatgpt-4o", "o1", "o3", "o4"];
+    let cl100k = ["gpt-4", "gpt-3.5", "gpt-35"];
+
+    if o200k.iter().any(|prefix| model.starts_with(prefix)) {
+        Some(o200k_base_singleton())
+    } else if cl100k.iter().any(|prefix| model.starts_with(prefix)) {
+        Some(cl100k_base_singleton())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FunctionDefinition, JsonSchema, Tool};
+
+    #[test]
+    fn test_model_limits() {
+        assert_eq!(
+            model_limits("gpt-4o-mini-2024-07-18").unwrap().model,
+            "gpt-4o-mini"
+        );
+        assert_eq!(model_limits("openai/gpt-4-0613").unwrap().model, "gpt-4");
+        assert_eq!(
+            model_limits("anthropic/claude-3-5-haiku-latest")
+                .unwrap()
+                .max_output_tokens,
+            8_192
+        );
+        assert!(model_limits("unknown-model").is_none());
+
+        // Other models sharing a prefix do not inherit its limits
+        assert_eq!(model_limits("gpt-4.5-preview").unwrap().model, "gpt-4.5");
+        assert_eq!(
+            model_limits("gpt-4-32k-0613").unwrap().context_window,
+            32_768
+        );
+        assert_eq!(
+            model_limits("o1-mini-2024-09-12").unwrap().context_window,
+            128_000
+        );
+        assert_eq!(
+            model_limits("claude-opus-4-1-20250805").unwrap().model,
+            "claude-opus-4"
+        );
+        assert!(model_limits("gpt-4o-audio-preview").is_none());
+    }
+
+    #[test]
+    fn test_fits_uses_the_model_limits() {
+        // About 10k tokens: too long for GPT-4, not for its larger variants
+        let input = InputObject::new(vec![Message::user("word ".repeat(10_000))]);
+        assert!(!estimate("gpt-4", &input).fits());
+        assert!(estimate("gpt-4-32k", &input).fits());
+        assert!(estimate("gpt-4.5-preview", &input).fits());
+    }
+
+    #[test]
+    fn test_heuristic_fallback() {
+        assert_eq!(count_tokens("unknown-model", ""), 0);
+        assert_eq!(count_tokens("unknown-model", "abcdefghi"), 3);
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn test_tokenizer() {
+        assert_eq!(count_tokens("gpt-4o", "Hello, world!"), 4);
+        assert_eq!(count_tokens("openai/gpt-4-turbo", "Hello, world!"), 4);
+    }
+
+    #[test]
+    fn test_estimate_input() {
+        let input = InputObject::new(vec![
+            Message::system("x".repeat(400)),
+            Message::user_with_parts(vec![
+                ContentPart::text("What is this?"),
+                ContentPart::image_url_with_detail("https://example.com/a.png", ImageDetail::Low),
+            ]),
+        ]);
+        let base = estimate("unknown-model", &input);
+        assert!(base.prompt_tokens > 100 + LOW_DETAIL_IMAGE_TOKENS);
+        assert_eq!(base.remaining(), None);
+        assert!(base.fits());
+
+        let tool = Tool::function(FunctionDefinition {
+            name: "lookup".to_string(),
+            description: Some("Look something up".to_string()),
+            parameters: JsonSchema {
+                schema_type: "object".to_string(),
+                properties: None,
+                required: None,
+                description: None,
+            },
+        });
+        let with_tools = estimate("unknown-model", &input.clone().with_tools(vec![tool]));
+        assert!(with_tools.prompt_tokens > base.prompt_tokens);
+    }
+
+    #[test]
+    fn test_fits_context_window() {
+        let input = InputObject::new(vec![Message::user("hello world ".repeat(10_000))]);
+        assert!(estimate("gpt-4o", &input).fits());
+        assert!(!estimate("gpt-4-0613", &input).fits());
+
+        let input = InputObject::new(vec![Message::user("Hello")]).with_max_tokens(10_000);
+        assert!(!estimate("gpt-4-0613", &input).fits());
+    }
+}
@@ -0,0 +1,685 @@
+use crate::{
+    client::{Edgee, Input},
+    error::{Error, Result},
+    models::{FunctionDefinition, Message, SendResponse, Tool, ToolCall, Usage},
+};
+use futures::future::{join_all, BoxFuture, FutureExt};
+use std::{collections::HashMap, fmt, future::Future, sync::Arc};
+
+#[cfg(feature = "schemars")]
+use crate::models::JsonSchema;
+
+/// Tool whose definition and argument parsing both derive from a Rust type
+///
+/// The JSON schema sent to the model is generated from [`EdgeeTool::Args`],
+/// and tool calls are deserialized back into the same type, so the two can
+/// never drift apart. Doc comments on the arguments become descriptions in
+/// the schema.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::{tools::EdgeeTool, Edgee, InputObject, Message};
+/// use schemars::JsonSchema;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, JsonSchema)]
+/// struct WeatherArgs {
+///     /// The city and state, e.g. San Francisco, CA
+///     location: String,
+/// }
+///
+/// struct GetWeather;
+///
+/// impl EdgeeTool for GetWeather {
+///     type Args = WeatherArgs;
+///     const NAME: &'static str = "get_weather";
+///     const DESCRIPTION: Option<&'static str> = Some("Get the current weather for a location");
+/// }
+///
+/// let client = Edgee::from_env()?;
+/// let input = InputObject::new(vec![Message::user("What's the weather in Paris?")])
+///     .with_tools(vec![GetWeather::definition()]);
+///
+/// let response = client.send("gpt-4o", input).await?;
+/// for call in response.tool_calls().into_iter().flatten() {
+///     if GetWeather::matches(call) {
+///         let args = GetWeather::parse_args(call)?;
+///         println!("Weather requested for {}", args.location);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "schemars")]
+pub trait EdgeeTool {
+    /// Arguments of the tool
+    type Args: serde::de::DeserializeOwned + schemars::JsonSchema;
+
+    /// Name of the function exposed to the model
+    const NAME: &'static str;
+
+    /// Description of the function exposed to the model
+    const DESCRIPTION: Option<&'static str> = None;
+
+    /// Build the tool definition sent to the model
+    fn definition() -> Tool {
+        Tool::function(FunctionDefinition {
+            name: Self::NAME.to_string(),
+            description: Self::DESCRIPTION.map(str::to_string),
+            parameters: schema_for::<Self::Args>(),
+        })
+    }
+
+    /// Whether a tool call targets this tool
+    fn matches(call: &ToolCall) -> bool {
+        call.function.name == Self::NAME
+    }
+
+    /// Deserialize the arguments of a tool call
+    fn parse_args(call: &ToolCall) -> Result<Self::Args> {
+        call.parse_arguments()
+    }
+}
+
+/// Generate the parameters schema of a function from a Rust type
+///
+/// Subschemas are inlined, as [`JsonSchema`] has no room for definitions.
+/// Recursive types cannot be inlined: their definitions are copied under
+/// `$defs` into each property using them, and the references rewritten to
+/// point there. Only the `type`, `properties`, `required` and `description`
+/// of the root schema are kept.
+#[cfg(feature = "schemars")]
+pub fn schema_for<T: schemars::JsonSchema>() -> JsonSchema {
+    let schema = schemars::generate::SchemaSettings::draft2020_12()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator()
+        .into_root_schema_for::<T>();
+
+    let field = |name: &str| schema.get(name).cloned();
+    let defs = field("$defs").filter(|defs| defs.as_object().is_some_and(|d| !d.is_empty()));
+    let properties = field("properties")
+        .and_then(|p| serde_json::from_value::<HashMap<String, serde_json::Value>>(p).ok())
+        .map(|mut properties| {
+            if let Some(defs) = &defs {
+                for (name, property) in &mut properties {
+                    embed_definitions(name, property, defs);
+                }
+            }
+            properties
+        });
+
+    JsonSchema {
+        schema_type: field("type")
+            .and_then(|t| t.as_str().map(str::to_string))
+            .unwrap_or_else(|| "object".to_string()),
+        properties,
+        required: field("required").and_then(|r| serde_json::from_value(r).ok()),
+        description: field("description").and_then(|d| d.as_str().map(str::to_string)),
+    }
+}
+
+/// Copy the root definitions into a property schema referencing them
+///
+/// References to `#/$defs/...` are rewritten to the copy under
+/// `#/properties/<name>/$defs/...`.
+#[cfg(feature = "schemars")]
+fn embed_definitions(name: &str, property: &mut serde_json::Value, defs: &serde_json::Value) {
+    use serde_json::Value;
+
+    fn rewrite_refs(value: &mut Value, prefix: &str) -> bool {
+        match value {
+            Value::Object(object) => {
+                let mut found = false;
+                if let Some(Value::String(reference)) = object.get_mut("$ref") {
+                    if let Some(definition) = reference.strip_prefix("#/$defs/") {
+                        *reference = format!("{}{}", prefix, definition);
+                        found = true;
+                    }
+                }
+                for (key, child) in object.iter_mut() {
+                    if key != "$ref" {
+                        found |= rewrite_refs(child, prefix);
+                    }
+                }
+                found
+            }
+            Value::Array(items) => items
+                .iter_mut()
+                .fold(false, |found, item| rewrite_refs(item, prefix) | found),
+            _ => false,
+        }
+    }
+
+    let pointer = name.replace('~', "~0").replace('/', "~1");
+    let prefix = format!("#/properties/{}/$defs/", pointer);
+    if !rewrite_refs(property, &prefix) {
+        return;
+    }
+    let mut defs = defs.clone();
+    rewrite_refs(&mut defs, &prefix);
+    if let Value::Object(object) = property {
+        object.insert("$defs".to_string(), defs);
+    }
+}
+
+/// Handler executing a tool call and returning the result sent to the model
+type ToolHandler = Arc<dyn Fn(ToolCall) -> BoxFuture<'static, String> + Send + Sync>;
+
+/// Runs the tool calling loop on top of [`Edgee::send`]
+///
+/// The runner sends the input along with the registered tools, executes the
+/// tool calls made by the model, appends their results to the conversation
+/// and sends it again, until the model replies without calling any tool.
+///
+/// Handler errors, unknown tools and invalid arguments are reported to the
+/// model as tool results, so it can correct itself.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use edgee::{tools::ToolRunner, Edgee, FunctionDefinition, JsonSchema};
+///
+/// let client = Edgee::from_env()?;
+/// let clock = FunctionDefinition {
+///     name: "get_time".to_string(),
+///     description: Some("Get the current time".to_string()),
+///     parameters: JsonSchema {
+///         schema_type: "object".to_string(),
+///         properties: None,
+///         required: None,
+///         description: None,
+///     },
+/// };
+///
+/// let output = ToolRunner::new(client)
+///     .register(clock, |_args| async { Ok::<_, String>("12:00".to_string()) })
+///     .run("gpt-4o", "What time is it?")
+///     .await?;
+///
+/// println!("{}", output.response.text().unwrap_or(""));
+/// println!("{} tokens used", output.usage.total_tokens);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ToolRunner {
+    client: Edgee,
+    tools: Vec<Tool>,
+    handlers: HashMap<String, ToolHandler>,
+    max_iterations: usize,
+    parallel: bool,
+}
+
+/// Result of a [`ToolRunner`] run
+#[derive(Debug, Clone)]
+pub struct ToolRunOutput {
+    /// Final response of the model
+    pub response: SendResponse,
+    /// Full transcript, including tool calls, tool results and the final answer
+    pub messages: Vec<Message>,
+    /// Token usage aggregated over all the requests
+    pub usage: Usage,
+    /// Number of requests sent
+    pub iterations: usize,
+}
+
+impl ToolRunner {
+    /// Create a runner with no tools, allowing 10 iterations
+    pub fn new(client: Edgee) -> Self {
+        Self {
+            client,
+            tools: Vec::new(),
+            handlers: HashMap::new(),
+            max_iterations: 10,
+            parallel: true,
+        }
+    }
+
+    /// Register a tool and the async handler executing it
+    ///
+    /// The handler receives the JSON arguments of the call.
+    pub fn register<F, Fut, E>(mut self, definition: FunctionDefinition, handler: F) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::result::Result<String, E>> + Send + 'static,
+        E: fmt::Display,
+    {
+        let handler = Arc::new(handler);
+        self.insert(
+            Tool::function(definition),
+            Arc::new(move |call: ToolCall| {
+                let args = call.parse_arguments::<serde_json::Value>();
+                let handler = handler.clone();
+                async move {
+                    match args {
+                        Ok(args) => report(handler(args).await),
+                        Err(e) => format!("Error: {}", e),
+                    }
+                }
+                .boxed()
+            }),
+        );
+        self
+    }
+
+    /// Register a typed tool and the async handler executing it
+    ///
+    /// The handler receives the arguments of the call, deserialized into
+    /// [`EdgeeTool::Args`].
+    #[cfg(feature = "schemars")]
+    pub fn register_tool<T, F, Fut, E>(mut self, handler: F) -> Self
+    where
+        T: EdgeeTool,
+        T::Args: Send + 'static,
+        F: Fn(T::Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::result::Result<String, E>> + Send + 'static,
+        E: fmt::Display,
+    {
+        let handler = Arc::new(handler);
+        self.insert(
+            T::definition(),
+            Arc::new(move |call: ToolCall| {
+                let args = T::parse_args(&call);
+                let handler = handler.clone();
+                async move {
+                    match args {
+                        Ok(args) => report(handler(args).await),
+                        Err(e) => format!("Error: {}", e),
+                    }
+                }
+                .boxed()
+            }),
+        );
+        self
+    }
+
+    /// Set the maximum number of requests sent in a run
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Execute the tool calls of a single response concurrently (default) or one by one
+    pub fn with_parallel_execution(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Run the tool calling loop until the model gives a final answer
+    ///
+    /// Fails with [`Error::MaxIterations`] if the model still calls tools
+    /// after the maximum number of iterations. The error carries the
+    /// transcript and the usage of the run.
+    pub async fn run(
+        &self,
+        model: impl Into<String>,
+        input: impl Into<Input>,
+    ) -> Result<ToolRunOutput> {
+        let model = model.into();
+        let mut input = input.into().into_object();
+
+        let tools = input.tools.get_or_insert_with(Vec::new);
+        for tool in &self.tools {
+            if !tools.iter().any(|t| t.function.name == tool.function.name) {
+                tools.push(tool.clone());
+            }
+        }
+
+        let mut usage = Usage::default();
+        for iteration in 1..=self.max_iterations {
+            let response = self.client.send(model.clone(), input.clone()).await?;
+            if let Some(response_usage) = &response.usage {
+                usage += response_usage;
+            }
+
+            let Some(message) = response.message().cloned() else {
+                return Err(Error::EmptyResponse(Box::new(response)));
+            };
+            let tool_calls = message.tool_calls.clone().unwrap_or_default();
+            input.messages.push(message);
+
+            if tool_calls.is_empty() {
+                return Ok(ToolRunOutput {
+                    response,
+                    messages: input.messages,
+                    usage,
+                    iterations: iteration,
+                });
+            }
+
+            let results = if self.parallel {
+                join_all(tool_calls.iter().map(|call| self.execute(call))).await
+            } else {
+                let mut results = Vec::with_capacity(tool_calls.len());
+                for call in &tool_calls {
+                    results.push(self.execute(call).await);
+                }
+                results
+            };
+
+            for (call, result) in tool_calls.iter().zip(results) {
+                input.messages.push(Message::tool(call.id.clone(), result));
+            }
+        }
+
+        Err(Error::MaxIterations {
+            limit: self.max_iterations,
+            messages: input.messages,
+            usage,
+        })
+    }
+
+    /// Register a tool, replacing any tool with the same name
+    fn insert(&mut self, tool: Tool, handler: ToolHandler) {
+        let name = tool.function.name.clone();
+        self.tools.retain(|t| t.function.name != name);
+        self.tools.push(tool);
+        self.handlers.insert(name, handler);
+    }
+
+    /// Execute a single tool call
+    async fn execute(&self, call: &ToolCall) -> String {
+        match self.handlers.get(&call.function.name) {
+            Some(handler) => handler(call.clone()).await,
+            None => format!("Error: unknown tool `{}`", call.function.name),
+        }
+    }
+}
+
+impl fmt::Debug for ToolRunner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolRunner")
+            .field("tools", &self.tools)
+            .field("max_iterations", &self.max_iterations)
+            .field("parallel", &self.parallel)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Turn the result of a handler into the content sent to the model
+fn report<E: fmt::Display>(result: std::result::Result<String, E>) -> String {
+    result.unwrap_or_else(|e| format!("Error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EdgeeConfig;
+
+    #[cfg(feature = "schemars")]
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    enum Unit {
+        #[serde(rename = "celsius")]
+        Celsius,
+        #[serde(rename = "fahrenheit")]
+        Fahrenheit,
+    }
+
+    /// Weather lookup arguments
+    #[cfg(feature = "schemars")]
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct WeatherArgs {
+        /// The city and state
+        location: String,
+        unit: Option<Unit>,
+    }
+
+    #[cfg(feature = "schemars")]
+    struct GetWeather;
+
+    #[cfg(feature = "schemars")]
+    impl EdgeeTool for GetWeather {
+        type Args = WeatherArgs;
+        const NAME: &'static str = "get_weather";
+        const DESCRIPTION: Option<&'static str> = Some("Get the weather");
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_definition_from_type() {
+        let tool = GetWeather::definition();
+        assert_eq!(tool.tool_type, "function");
+        assert_eq!(tool.function.name, "get_weather");
+        assert_eq!(
+            tool.function.description.as_deref(),
+            Some("Get the weather")
+        );
+
+        let parameters = tool.function.parameters;
+        assert_eq!(parameters.schema_type, "object");
+        assert_eq!(parameters.required, Some(vec!["location".to_string()]));
+        assert_eq!(
+            parameters.description.as_deref(),
+            Some("Weather lookup arguments")
+        );
+
+        let properties = parameters.properties.unwrap();
+        assert_eq!(properties["location"]["type"], "string");
+        assert_eq!(properties["location"]["description"], "The city and state");
+        // Referenced types are inlined
+        assert!(properties["unit"].to_string().contains("fahrenheit"));
+        assert!(!properties["unit"].to_string().contains("$ref"));
+    }
+
+    #[cfg(feature = "schemars")]
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct Filter {
+        field: String,
+        children: Vec<Filter>,
+    }
+
+    #[cfg(feature = "schemars")]
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[allow(dead_code)]
+    struct SearchArgs {
+        query: String,
+        filter: Option<Filter>,
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_definition_of_recursive_type() {
+        fn refs<'a>(value: &'a serde_json::Value, found: &mut Vec<&'a str>) {
+            match value {
+                serde_json::Value::Object(object) => {
+                    if let Some(reference) = object.get("$ref").and_then(|r| r.as_str()) {
+                        found.push(reference);
+                    }
+                    object.values().for_each(|child| refs(child, found));
+                }
+                serde_json::Value::Array(items) => items.iter().for_each(|item| refs(item, found)),
+                _ => {}
+            }
+        }
+
+        let parameters = serde_json::to_value(schema_for::<SearchArgs>()).unwrap();
+        let mut found = Vec::new();
+        refs(&parameters, &mut found);
+
+        // Every reference resolves within the parameters schema
+        assert!(!found.is_empty());
+        for reference in found {
+            let target = reference.strip_prefix('#').unwrap();
+            assert!(
+                parameters.pointer(target).is_some(),
+                "dangling reference {}",
+                reference
+            );
+        }
+        assert!(parameters["properties"]["query"].get("$defs").is_none());
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_parse_args() {
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            call_type: "function".to_string(),
+            function: crate::models::FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: r#"{"location": "Paris", "unit": "celsius"}"#.to_string(),
+            },
+        };
+        assert!(GetWeather::matches(&call));
+        assert_eq!(GetWeather::parse_args(&call).unwrap().location, "Paris");
+
+        let call = ToolCall {
+            function: crate::models::FunctionCall {
+                name: "get_weather".to_string(),
+                arguments: r#"{"city": "Paris"}"#.to_string(),
+            },
+            ..call
+        };
+        let err = GetWeather::parse_args(&call).err().unwrap();
+        assert!(
+            matches!(err, crate::Error::ToolArguments { ref name, .. } if name == "get_weather")
+        );
+    }
+
+    fn response(message: serde_json::Value) -> String {
+        serde_json::json!({
+            "id": "test-id",
+            "object": "chat.completion",
+            "created": 1234567890,
+            "model": "gpt-4o",
+            "choices": [{"index": 0, "message": message, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15}
+        })
+        .to_string()
+    }
+
+    fn definition(name: &str) -> FunctionDefinition {
+        FunctionDefinition {
+            name: name.to_string(),
+            description: None,
+            parameters: crate::models::JsonSchema {
+                schema_type: "object".to_string(),
+                properties: None,
+                required: None,
+                description: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_executes_tool_calls() {
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(response(serde_json::json!({
+                "role": "assistant",
+                "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "add", "arguments": "{\"a\": 2, \"b\": 3}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "missing", "arguments": "{}"}}
+                ]
+            })))
+            .expect(1)
+            .create_async()
+            .await;
+        let second = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "messages": [
+                    {"role": "user", "content": "What is 2 + 3?"},
+                    {"role": "assistant"},
+                    {"role": "tool", "tool_call_id": "call_1", "content": "5"},
+                    {"role": "tool", "tool_call_id": "call_2", "content": "Error: unknown tool `missing`"}
+                ]
+            })))
+            .with_body(response(serde_json::json!({"role": "assistant", "content": "It is 5"})))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let output = ToolRunner::new(client)
+            .register(definition("add"), |args| async move {
+                let sum = args["a"].as_i64().unwrap_or(0) + args["b"].as_i64().unwrap_or(0);
+                Ok::<_, String>(sum.to_string())
+            })
+            .run("gpt-4o", "What is 2 + 3?")
+            .await
+            .unwrap();
+
+        assert_eq!(output.response.text(), Some("It is 5"));
+        assert_eq!(output.iterations, 2);
+        assert_eq!(output.messages.len(), 5);
+        assert_eq!(output.usage.total_tokens, 30);
+        first.assert_async().await;
+        second.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_run_rejects_empty_response() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(
+                serde_json::json!({
+                    "id": "empty", "object": "chat.completion", "created": 0,
+                    "model": "gpt-4o", "choices": []
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let err = ToolRunner::new(client)
+            .run("gpt-4o", "Hi")
+            .await
+            .unwrap_err();
+
+        match err {
+            Error::EmptyResponse(response) => assert_eq!(response.id, "empty"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_at_max_iterations() {
+        let mut server = mockito::Server::new_async().await;
+        let looping = server
+            .mock("POST", "/v1/chat/completions")
+            .with_body(response(serde_json::json!({
+                "role": "assistant",
+                "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "ping", "arguments": "{}"}}
+                ]
+            })))
+            .expect(3)
+            .create_async()
+            .await;
+
+        let client = Edgee::new(EdgeeConfig::new("test-key").with_base_url(server.url()));
+        let err = ToolRunner::new(client)
+            .register(definition("ping"), |_| async { Err::<String, _>("boom") })
+            .with_max_iterations(3)
+            .run("gpt-4o", "Ping forever")
+            .await
+            .unwrap_err();
+
+        match err {
+            Error::MaxIterations {
+                limit,
+                messages,
+                usage,
+            } => {
+                assert_eq!(limit, 3);
+                // The prompt, then a tool call and its result per iteration
+                assert_eq!(messages.len(), 7);
+                assert_eq!(messages[6].tool_call_id.as_deref(), Some("call_1"));
+                assert_eq!(usage.total_tokens, 3 * 15);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+        looping.assert_async().await;
+    }
+}